@@ -0,0 +1,11 @@
+fn mainImage(fragCoord: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    let uv = (fragCoord * 2. - size) / size.y;
+
+    let q = vec2<f32>(plix_fbm(uv * 2. + data.t * 0.1), plix_fbm(uv * 2. + vec2<f32>(5.2, 1.3)));
+    let v = plix_fbm(uv * 2. + q * 2. + data.t * 0.2);
+
+    let sky = mix(vec3<f32>(0.1, 0.25, 0.5), vec3<f32>(0.6, 0.8, 1.), uv.y * 0.5 + 0.5);
+    let color = mix(sky, vec3<f32>(1.), smoothstep(0.35, 0.8, v));
+    return vec4<f32>(color, 1.);
+}
@@ -0,0 +1,83 @@
+// Diffuse path tracer. Every step adds one sample per pixel, the prelude averages them
+// until time, params or the size change
+
+struct Hit {
+    t: f32,
+    normal: vec3<f32>,
+    albedo: vec3<f32>,
+};
+
+var<private> rng: u32;
+
+fn random() -> f32 {
+    rng = rng * 747796405u + 2891336453u;
+    let word = ((rng >> ((rng >> 28u) + 4u)) ^ rng) * 277803737u;
+    return f32((word >> 22u) ^ word) / 4294967296.;
+}
+
+fn random_direction() -> vec3<f32> {
+    let z = random() * 2. - 1.;
+    let a = random() * 6.28318;
+    let r = sqrt(1. - z * z);
+    return vec3<f32>(r * cos(a), r * sin(a), z);
+}
+
+fn sphere(hit: ptr<function, Hit>, origin: vec3<f32>, dir: vec3<f32>, center: vec3<f32>, radius: f32, albedo: vec3<f32>) {
+    let oc = origin - center;
+    let b = dot(oc, dir);
+    let h = b * b - dot(oc, oc) + radius * radius;
+    if h < 0. {
+        return;
+    }
+    let t = -b - sqrt(h);
+    if t > 0.001 && t < (*hit).t {
+        (*hit).t = t;
+        (*hit).normal = (origin + dir * t - center) / radius;
+        (*hit).albedo = albedo;
+    }
+}
+
+fn scene(origin: vec3<f32>, dir: vec3<f32>) -> Hit {
+    var hit = Hit(1e9, vec3<f32>(0.), vec3<f32>(0.));
+    let t = data.t * 0.5;
+    sphere(&hit, origin, dir, vec3<f32>(0., -1000., 0.), 999., vec3<f32>(0.6));
+    sphere(&hit, origin, dir, vec3<f32>(0., 0., 0.), 1., plix_palette(0.1));
+    sphere(&hit, origin, dir, vec3<f32>(2. * cos(t), -0.5, 2. * sin(t)), 0.5, plix_palette(0.5));
+    sphere(&hit, origin, dir, vec3<f32>(-2. * cos(t), -0.6, -2. * sin(t)), 0.4, plix_palette(0.8));
+    return hit;
+}
+
+fn sky(dir: vec3<f32>) -> vec3<f32> {
+    return mix(vec3<f32>(1.), vec3<f32>(0.5, 0.7, 1.), dir.y * 0.5 + 0.5);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= data.width || global_id.y >= data.height {
+        return;
+    }
+    rng = (global_id.y * data.width + global_id.x) ^ (sample.seed * 1664525u);
+
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    // Jitters inside the pixel, so edges get anti-aliased too
+    let pixel = vec2<f32>(global_id.xy) + vec2<f32>(random(), random());
+    let uv = (pixel * 2. - size) / size.y;
+
+    var origin = vec3<f32>(0., 0.5, 5.);
+    var dir = normalize(vec3<f32>(uv, -2.5) - vec3<f32>(0., 0.2, 0.));
+    var throughput = vec3<f32>(1.);
+    var color = vec3<f32>(0.);
+    for (var i = 0u; i < u32(params.bounces); i++) {
+        let hit = scene(origin, dir);
+        if hit.t >= 1e9 {
+            color = throughput * sky(dir);
+            break;
+        }
+        origin += dir * hit.t;
+        dir = normalize(hit.normal + random_direction());
+        throughput *= hit.albedo;
+    }
+
+    // Averaging after the gamma curve is slightly off, but converges just as well
+    accumulate(global_id.xy, vec4<f32>(sqrt(color), 1.));
+}
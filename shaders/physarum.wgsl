@@ -0,0 +1,77 @@
+// Slime mold: agents steer towards the trail and leave more of it behind
+struct Agent {
+    pos: vec2<f32>,
+    /// In turns
+    angle: f32,
+    _pad: f32,
+}
+
+const SENSE_ANGLE: f32 = 0.08;
+const SENSE_DISTANCE: f32 = 0.03;
+const TURN: f32 = 0.04;
+const SPEED: f32 = 0.002;
+/// Trail is fixed point, so deposits can be added atomically
+const DEPOSIT: u32 = 256u;
+
+fn cell(pos: vec2<f32>) -> u32 {
+    let c = vec2<u32>(fract(pos) * f32(GRID)) % GRID;
+    return c.x + c.y * GRID;
+}
+
+fn direction(angle: f32) -> vec2<f32> {
+    return vec2<f32>(cos(angle * 6.28318), sin(angle * 6.28318));
+}
+
+fn sense(agent: Agent, offset: f32) -> u32 {
+    return atomicLoad(&trail[cell(agent.pos + SENSE_DISTANCE * direction(agent.angle + offset))]);
+}
+
+// GRID and AGENTS are constants from the toml, so the dispatch sizes can use them
+
+@compute @workgroup_size(64)
+fn fade(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= GRID * GRID {
+        return;
+    }
+    atomicStore(&trail[i], atomicLoad(&trail[i]) * 9u / 10u);
+}
+
+@compute @workgroup_size(64)
+fn steer(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= AGENTS {
+        return;
+    }
+    var agent = agents[i];
+    let left = sense(agent, SENSE_ANGLE);
+    let ahead = sense(agent, 0.);
+    let right = sense(agent, -SENSE_ANGLE);
+    if ahead < left || ahead < right {
+        agent.angle += select(-TURN, TURN, left > right);
+    }
+    agent.pos = fract(agent.pos + SPEED * direction(agent.angle));
+    agents[i] = agent;
+}
+
+@compute @workgroup_size(64)
+fn deposit(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= AGENTS {
+        return;
+    }
+    atomicAdd(&trail[cell(agents[i].pos)], DEPOSIT);
+}
+
+@compute @workgroup_size(8, 8)
+fn draw(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= data.width || id.y >= data.height {
+        return;
+    }
+    let uv = vec2<f32>(id.xy) / vec2<f32>(f32(data.width), f32(data.height));
+    let v = f32(atomicLoad(&trail[cell(uv)])) / f32(DEPOSIT);
+    let density = 1. - exp(-0.3 * v);
+    let color = plix_palette(0.6 + 0.15 * density) * density;
+
+    textureStore(texture, id.xy, vec4<f32>(color, 1.));
+}
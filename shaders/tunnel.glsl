@@ -0,0 +1,14 @@
+// Shadertoy style GLSL, translated to WGSL when loaded
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = (2.0 * fragCoord - iResolution.xy) / iResolution.y;
+
+    float r = length(uv);
+    float a = atan(uv.y, uv.x);
+    vec2 st = vec2(a / 3.14159, 0.4 / r + iTime * 0.5);
+
+    float stripes = smoothstep(0.4, 0.6, abs(fract(st.x * 6.0) - 0.5) * 2.0);
+    float rings = smoothstep(0.4, 0.6, abs(fract(st.y * 4.0) - 0.5) * 2.0);
+    vec3 col = mix(vec3(0.9, 0.4, 0.1), vec3(0.1, 0.3, 0.8), stripes * rings);
+
+    fragColor = vec4(col * min(r * 1.5, 1.0), 1.0);
+}
@@ -1,32 +1,69 @@
-use std::sync::mpsc;
+use std::{sync::mpsc, time::Duration};
 
 use crate::compute::Compute;
+use crate::editor::Editor;
 use crate::export::Export;
-use crate::renderer::Renderer;
+use crate::gpu::{AdapterPanel, Gpu};
+use crate::inspector::Inspector;
+use crate::live::Live;
+use crate::playback::Playback;
+use crate::prelude::Shader;
+use crate::profiler::{PerfSample, Profiler};
+use crate::renderer::{Renderer, ViewUniform};
+use crate::settings::Settings;
 use crate::shader_manager::ShaderManager;
 use eframe::{egui, emath, CreationContext};
 use eframe::{egui_wgpu, wgpu};
 
 pub struct App {
+    adapter_panel: AdapterPanel,
+    editor: Editor,
     export: Export,
+    inspector: Inspector,
+    live: Live,
+    compile_tx: mpsc::Sender<Duration>,
+    compile_rx: mpsc::Receiver<Duration>,
+    profiler: Profiler,
+    perf_tx: mpsc::Sender<PerfSample>,
     shader_manager: ShaderManager,
     shader_manager_rx: mpsc::Receiver<String>,
 
+    playback: Playback,
     show_menu: bool,
 }
 
 impl App {
-    pub fn new(cc: &CreationContext) -> Option<Self> {
+    pub fn new(cc: &CreationContext, adapter: Option<String>) -> Option<Self> {
+        let settings = Settings::load(cc.storage);
+        let wgpu_render_state = cc.wgpu_render_state.as_ref()?;
+
+        let gpu = Gpu::from_render_state(wgpu_render_state);
+
         let (tx, rx) = mpsc::channel();
-        let shader_manager = ShaderManager::new(tx)?;
+        let mut shader_manager = ShaderManager::new(tx, gpu.clone())?;
+        shader_manager.restore(settings.shader.as_deref(), &settings.params);
+        // Compute starts with the restored shader, earlier selections don't need a reload
+        while rx.try_recv().is_ok() {}
 
-        let wgpu_render_state = cc.wgpu_render_state.as_ref()?;
-        let renderer = Renderer::new(wgpu_render_state, [10, 10]);
-        let compute = Compute::new(
+        let (pixel_tx, pixel_rx) = mpsc::channel();
+        // An unsupported format is one of the shader's issues, it starts in rgba8 instead
+        let format = gpu
+            .output_format(shader_manager.meta().format())
+            .unwrap_or(wgpu::TextureFormat::Rgba8Unorm);
+        let renderer = Renderer::new(wgpu_render_state, [10, 10], format, pixel_tx);
+        let mut compute = Compute::new(
             &wgpu_render_state.device,
+            &wgpu_render_state.queue,
             &renderer.texture,
             shader_manager.selected(),
         );
+        compute.enable_timing(&wgpu_render_state.device, &wgpu_render_state.queue);
+        compute.enable_background_compile(
+            wgpu_render_state.device.clone(),
+            wgpu_render_state.queue.clone(),
+        );
+        let (perf_tx, perf_rx) = mpsc::channel();
+        let (compile_tx, compile_rx) = mpsc::channel();
 
         wgpu_render_state
             .renderer
@@ -34,40 +71,172 @@ impl App {
             .callback_resources
             .insert((renderer, compute));
 
+        let mut editor = Editor::new(settings.show_editor);
+        editor.open(shader_manager.selected());
+
         Some(Self {
-            export: Export::new(shader_manager.selected().into()),
+            adapter_panel: AdapterPanel::new(wgpu_render_state, adapter),
+            editor,
+            export: Export::new(
+                settings.export,
+                shader_manager.selected().into(),
+                shader_manager.meta(),
+                gpu,
+                wgpu_render_state
+                    .available_adapters
+                    .iter()
+                    .map(|a| a.get_info().name)
+                    .collect(),
+            ),
+            inspector: Inspector::new(pixel_rx),
+            live: Live::new(settings.live_mode),
+            compile_tx,
+            compile_rx,
+            profiler: Profiler::new(perf_rx),
+            perf_tx,
             shader_manager,
             shader_manager_rx: rx,
 
-            show_menu: true,
+            playback: settings.playback,
+            show_menu: settings.show_menu,
         })
     }
+
+    /// Everything saved between runs goes back to the defaults, the shader stays selected
+    fn reset_settings(&mut self) {
+        let defaults = Settings::default();
+        self.shader_manager.restore(None, &defaults.params);
+        self.export.reset(self.shader_manager.meta());
+        self.playback = defaults.playback;
+        self.show_menu = defaults.show_menu;
+        self.editor.visible = defaults.show_editor;
+        self.live.enabled = defaults.live_mode;
+    }
 }
 
 impl eframe::App for App {
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        let t = ctx.input(|i| i.time);
+    fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        let dt = ctx.input(|i| i.unstable_dt);
+        let mut screenshot = false;
+        let mut reset = false;
+        // Typing in the editor or a text field shouldn't trigger shortcuts
+        let shortcuts = !ctx.wants_keyboard_input();
         ctx.input(|i| {
+            if !shortcuts {
+                return;
+            }
+            if i.key_pressed(egui::Key::E) {
+                self.editor.visible = !self.editor.visible;
+            }
             if i.key_pressed(egui::Key::M) {
                 self.show_menu = !self.show_menu;
             }
+            if i.key_pressed(egui::Key::S) {
+                screenshot = true;
+            }
+            if i.key_pressed(egui::Key::P) {
+                self.profiler.visible = !self.profiler.visible;
+            }
+            if i.key_pressed(egui::Key::Space) {
+                self.playback.toggle();
+            }
+            if i.key_pressed(egui::Key::R) {
+                reset = true;
+            }
         });
+        self.playback.advance(dt);
+        let t = self.playback.time;
 
         self.shader_manager.update();
+        self.export.update();
+        self.profiler.update(dt);
+        while let Ok(time) = self.compile_rx.try_recv() {
+            self.live.set_compile_time(time);
+        }
+
+        let was_live = self.live.enabled;
         if self.show_menu {
             egui::SidePanel::left("Left").show(ctx, |ui| {
-                self.shader_manager.render_ui(ui);
-                ui.add_space(40.);
-                self.export.render_save_ui(ui);
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.shader_manager.render_ui(ui);
+                    ui.add_space(40.);
+                    self.live.render_ui(ui);
+                    ui.add_space(40.);
+                    self.playback.render_ui(ui);
+                    ui.add_space(40.);
+                    self.inspector.render_ui(ui);
+                    ui.checkbox(&mut self.profiler.visible, "Performance overlay");
+                    ui.checkbox(&mut self.editor.visible, "Code editor");
+                    ui.add_space(40.);
+                    self.export.render_save_ui(ui);
+                    ui.add_space(40.);
+                    if let Some(adapter) = self.adapter_panel.render_ui(ui) {
+                        self.export.set_adapter(Some(adapter));
+                    }
+                    ui.add_space(40.);
+                    if ui.button("Reset settings").clicked() {
+                        self.reset_settings();
+                    }
+                });
             });
         }
 
-        let reload_shader = if let Ok(s) = self.shader_manager_rx.try_recv() {
-            self.export.set_shader(s.clone());
-            Some(s)
-        } else {
-            None
+        if self.export.take_screenshot_request() || screenshot {
+            if let Some(render_state) = frame.wgpu_render_state() {
+                let resources = render_state.renderer.read();
+                let (renderer, _): &(Renderer, Compute) =
+                    resources.callback_resources.get().unwrap();
+                self.export.screenshot(render_state, &renderer.texture);
+            }
+        }
+
+        let selected = self.shader_manager.selected().to_owned();
+        let mut reload_shader = match self.shader_manager_rx.try_recv() {
+            Ok(s) => {
+                self.editor.open(&s);
+                self.export
+                    .set_shader(s.clone(), self.shader_manager.meta());
+                if self.live.enabled {
+                    self.live
+                        .submit(&s, self.editor.unsaved_text(&s).map(String::from));
+                    None
+                } else {
+                    Some(Reload::Path(s))
+                }
+            }
+            // Switching live mode on shows unsaved edits, switching it off goes back to the file
+            _ if self.live.enabled && !was_live => {
+                self.live.submit(
+                    &selected,
+                    self.editor.unsaved_text(&selected).map(String::from),
+                );
+                None
+            }
+            _ if !self.live.enabled && was_live => Some(Reload::Path(selected.clone())),
+            _ => None,
         };
+        self.export.set_params(self.shader_manager.named_params());
+        reset |= self.shader_manager.take_reset();
+
+        if self.editor.visible {
+            egui::SidePanel::right("Editor")
+                .default_width(480.)
+                .show(ctx, |ui| {
+                    let diagnostics = self
+                        .live
+                        .diagnostics(&selected)
+                        .unwrap_or(self.shader_manager.diagnostics());
+                    self.editor.render_ui(ui, diagnostics);
+                });
+        }
+        if let Some((path, text)) = self.editor.take_edit() {
+            if self.live.enabled {
+                self.live.submit(&path, Some(text));
+            }
+        }
+        if let Some((path, shader)) = self.live.update() {
+            reload_shader = Some(Reload::Shader(path, Box::new(shader)));
+        }
 
         egui::CentralPanel::default()
             .frame(egui::Frame {
@@ -81,33 +250,80 @@ impl eframe::App for App {
             })
             .show(ctx, |ui| {
                 egui::Frame::canvas(ui.style()).show(ui, |ui| {
-                    self.custom_painting(ui, t, reload_shader);
+                    self.custom_painting(ui, t, reload_shader, reset);
                 });
             });
 
+        self.profiler.render_overlay(ctx);
+
         ctx.request_repaint();
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        Settings {
+            shader: Some(self.shader_manager.selected().into()),
+            params: self.shader_manager.named_params(),
+            export: self.export.data().clone(),
+            show_menu: self.show_menu,
+            show_editor: self.editor.visible,
+            live_mode: self.live.enabled,
+            playback: self.playback,
+        }
+        .save(storage);
+    }
 }
 
 impl App {
-    fn custom_painting(&mut self, ui: &mut egui::Ui, t: f64, reload_shader: Option<String>) {
+    fn custom_painting(
+        &mut self,
+        ui: &mut egui::Ui,
+        t: f64,
+        reload_shader: Option<Reload>,
+        reset: bool,
+    ) {
         let size = ui.available_size();
-        let (_, rect) = ui.allocate_space(size);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        self.shader_manager
+            .set_canvas_size([size.x as u32, size.y as u32]);
+        self.inspector
+            .handle_input(ui, &response, [size.x as u32, size.y as u32]);
 
         ui.painter().add(egui_wgpu::Callback::new_paint_callback(
             rect,
             RendererCallback {
                 reload_shader,
+                reset,
                 size,
+                view: self.inspector.view(),
+                pick: self.inspector.pick(),
+                mouse: self.inspector.mouse(),
+                params: self.shader_manager.params().to_vec(),
+                perf_tx: self.perf_tx.clone(),
+                compile_tx: self.compile_tx.clone(),
                 t,
             },
         ));
     }
 }
 
+/// Shader the preview switches to this frame
+enum Reload {
+    /// Loaded from the file
+    Path(String),
+    /// Already validated by live mode, possibly from unsaved edits
+    Shader(String, Box<Shader>),
+}
+
 pub struct RendererCallback {
-    reload_shader: Option<String>,
+    reload_shader: Option<Reload>,
+    reset: bool,
     size: emath::Vec2,
+    view: ViewUniform,
+    pick: Option<[u32; 2]>,
+    mouse: [f32; 4],
+    params: Vec<f32>,
+    perf_tx: mpsc::Sender<PerfSample>,
+    compile_tx: mpsc::Sender<Duration>,
 
     t: f64,
 }
@@ -123,17 +339,38 @@ impl egui_wgpu::CallbackTrait for RendererCallback {
     ) -> Vec<wgpu::CommandBuffer> {
         let (renderer, compute): &mut (Renderer, Compute) = resources.get_mut().unwrap();
 
-        if let Some(s) = self.reload_shader.as_ref() {
-            compute.reload_shader(device, s);
+        let compile_time = match &self.reload_shader {
+            Some(Reload::Path(path)) => compute.reload_shader(device, queue, path),
+            Some(Reload::Shader(path, shader)) => {
+                compute.set_shader(device, queue, path, *shader.clone())
+            }
+            None => None,
+        };
+        if let Some(time) = compile_time.or(compute.poll_compiled(device, queue)) {
+            let _ = self.compile_tx.send(time);
+        }
+        if self.reset {
+            compute.reset(device, queue);
         }
 
-        if renderer.check_resize(device, [self.size.x as u32, self.size.y as u32]) {
-            compute.update_texture(device, &renderer.texture);
+        let size = [self.size.x as u32, self.size.y as u32];
+        if renderer.check_resize(device, size, compute.format()) {
+            compute.update_texture(device, queue, &renderer.texture);
             compute.update_texture_size(queue, [renderer.texture.width, renderer.texture.height]);
         }
         compute.update_time(queue, self.t as f32);
+        compute.update_mouse(queue, self.mouse);
+        compute.set_params(queue, &self.params);
+        renderer.update_view(queue, self.view);
 
         compute.step(device, queue, None);
+        let _ = self.perf_tx.send(PerfSample {
+            gpu: compute.poll_timing(),
+            dispatch: compute.dispatch_sizes(),
+            entry_points: compute.entry_points(),
+            samples: compute.samples(),
+        });
+        renderer.pick_pixel(device, queue, self.pick);
 
         Vec::new()
     }
@@ -1,30 +1,395 @@
-use crate::texture::Texture;
+use crate::{
+    gpu,
+    prelude::{self, Shader, ShaderKind},
+    profiler::{GpuTime, GpuTimer},
+    reflect,
+    renderer::{Vertex, INDICES, VERTICES},
+    shader_meta::{self, ClearOn, OutputFormat, ShaderMeta},
+    texture::Texture,
+};
 use bytemuck::{Pod, Zeroable};
 use eframe::wgpu::{self, util::DeviceExt};
-use pollster::FutureExt;
+use std::{
+    mem::offset_of,
+    num::NonZeroU64,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Number of `channel` textures
+const CHANNELS: u32 = 4;
+/// Bytes of the float sums `accumulate()` keeps per pixel
+const SUM_SIZE: u64 = 16;
+
+enum Pipeline {
+    /// One per entry point, dispatched in order
+    Compute(Vec<wgpu::ComputePipeline>),
+    /// Draws the full screen quad into `target`
+    Fragment(wgpu::RenderPipeline),
+}
+
+/// How compute shaders bind the output texture at `@group(1)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputAccess {
+    WriteOnly,
+    /// `previous()` reads the output itself, where the device supports it
+    ReadWrite,
+    /// `previous()` reads a copy of the output made after every step
+    Copy,
+}
+
+impl OutputAccess {
+    /// Picks the access for `shader` and returns the source to compile for an output
+    /// texture of `format`
+    fn pick(shader: Shader, format: wgpu::TextureFormat, read_write: bool) -> (Self, String) {
+        let format = OutputFormat::from_texture_format(format).unwrap_or_default();
+        let source = prelude::with_output_format(&shader.source, format);
+        if shader.kind == ShaderKind::Fragment || !shader.meta.output.read {
+            return (Self::WriteOnly, source);
+        }
+        match prelude::with_read_write_output(&source).filter(|_| read_write) {
+            Some(source) => (Self::ReadWrite, source),
+            None => (Self::Copy, source),
+        }
+    }
+
+    /// Shaders that accumulate also get the sums of their samples and the sample uniform
+    fn layout(
+        self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        accumulate: bool,
+    ) -> wgpu::BindGroupLayout {
+        let access = match self {
+            Self::ReadWrite => wgpu::StorageTextureAccess::ReadWrite,
+            Self::WriteOnly | Self::Copy => wgpu::StorageTextureAccess::WriteOnly,
+        };
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: Texture::storage_texture_binding_type(format, access),
+            count: None,
+        }];
+        if self == Self::Copy {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+        if accumulate {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        })
+    }
+}
+
+/// Only compute shaders get `accumulate()`, fragment shaders draw every step from scratch
+pub(crate) fn accumulates(kind: ShaderKind, meta: &ShaderMeta) -> bool {
+    kind == ShaderKind::Compute && meta.output.accumulate
+}
+
+/// Largest accumulation buffer the device can bind, in whole pixels
+fn max_accumulation_size(limits: &wgpu::Limits) -> u64 {
+    let max = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    max / SUM_SIZE * SUM_SIZE
+}
+
+/// Dispatch sizes of the passes of a compute shader that fail to evaluate for a
+/// `width` x `height` output, those passes don't run, or that go over the adapter's
+/// limit, those are cut down to it
+pub fn dispatch_issues(
+    kind: ShaderKind,
+    meta: &ShaderMeta,
+    limits: &wgpu::Limits,
+    width: u32,
+    height: u32,
+) -> Vec<String> {
+    if kind != ShaderKind::Compute {
+        return Vec::new();
+    }
+    let max = limits.max_compute_workgroups_per_dimension;
+    meta.passes()
+        .iter()
+        .filter_map(|pass| match meta.dispatch_size(pass, width, height) {
+            Ok(size) if size.iter().any(|s| *s > max) => Some(format!(
+                "Pass `{}` dispatches {} x {} x {} workgroups, the adapter allows {} per \
+                 dimension. Only part of it runs",
+                pass.entry_point, size[0], size[1], size[2], max
+            )),
+            Ok(_) => None,
+            Err(e) => Some(e),
+        })
+        .collect()
+}
+
+/// Why a `width` x `height` output can't be averaged everywhere. The sums of every pixel
+/// share one storage buffer, pixels past its limit show their latest sample instead
+pub fn accumulation_issue(limits: &wgpu::Limits, width: u32, height: u32) -> Option<String> {
+    let needed = width as u64 * height as u64 * SUM_SIZE;
+    let max = max_accumulation_size(limits);
+    (needed > max).then(|| {
+        format!(
+            "Averaging {}x{} pixels needs a {} MB buffer, the adapter allows {} MB. \
+             Only the first {} rows are averaged",
+            width,
+            height,
+            needed >> 20,
+            max >> 20,
+            max / SUM_SIZE / width.max(1) as u64
+        )
+    })
+}
+
+/// The output texture as far as compiling a shader for it is concerned
+#[derive(Clone, Copy)]
+struct Target {
+    format: wgpu::TextureFormat,
+    /// See `Texture::read_write`
+    read_write: bool,
+}
+
+/// Everything that depends on the shader, built before it replaces the current one
+struct Compiled {
+    pipeline: Pipeline,
+    compute_shader: wgpu::ShaderModule,
+    /// Output format of the shader's toml, which can differ from the current target
+    format: wgpu::TextureFormat,
+    kind: ShaderKind,
+    meta: ShaderMeta,
+    output: OutputAccess,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    channels_bind_group: wgpu::BindGroup,
+    storage: Storage,
+    time: Duration,
+}
+
+/// Storage buffers from the shader's toml, they keep their contents between steps
+struct Storage {
+    layout: wgpu::BindGroupLayout,
+    buffers: Vec<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Storage {
+    fn new(device: &wgpu::Device, meta: &ShaderMeta, kind: ShaderKind) -> Self {
+        let visibility = match kind {
+            ShaderKind::Compute => wgpu::ShaderStages::COMPUTE,
+            ShaderKind::Fragment => wgpu::ShaderStages::FRAGMENT,
+        };
+        let entries: Vec<_> = meta
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupLayoutEntry {
+                binding: binding as u32,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    // Lets pipeline creation fail if the declared type doesn't fit
+                    min_binding_size: NonZeroU64::new(buffer.byte_size()),
+                },
+                count: None,
+            })
+            .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
+
+        let buffers: Vec<_> = meta
+            .buffers
+            .iter()
+            .map(|buffer| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&buffer.name),
+                    contents: &buffer.initial_contents(),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+        let entries: Vec<_> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &entries,
+        });
+
+        Self {
+            layout,
+            buffers,
+            bind_group,
+        }
+    }
+
+    fn reset(&self, queue: &wgpu::Queue, meta: &ShaderMeta) {
+        for (buffer, declared) in self.buffers.iter().zip(meta.buffers.iter()) {
+            queue.write_buffer(buffer, 0, &declared.initial_contents());
+        }
+    }
+}
+
+/// Float sums of the samples of every pixel for shaders that accumulate, bound next to
+/// the output texture
+struct Accumulation {
+    sums: wgpu::Buffer,
+    /// Index and seed of the next sample
+    sample_buffer: wgpu::Buffer,
+    /// Samples in `sums`, the shader ignores them when it is 0
+    samples: u32,
+}
+
+impl Accumulation {
+    fn new(device: &wgpu::Device, target: &wgpu::Texture) -> Self {
+        let pixels = target.width() as u64 * target.height() as u64;
+        // Bigger than the limit is a validation error, the prelude skips pixels past the end
+        let size = (pixels * SUM_SIZE)
+            .min(max_accumulation_size(&device.limits()))
+            .max(SUM_SIZE);
+        Self {
+            sums: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("accumulation"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }),
+            sample_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 16,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            samples: 0,
+        }
+    }
+
+    fn write_sample(&self, queue: &wgpu::Queue) {
+        let sample = [self.samples, shader_meta::pcg(self.samples), 0, 0];
+        queue.write_buffer(&self.sample_buffer, 0, bytemuck::cast_slice(&sample));
+    }
+}
+
+struct CompileJob {
+    path: String,
+    /// `None` loads the file on the compile thread
+    shader: Option<Shader>,
+    /// Data, empty and channels, the output texture layout depends on the shader
+    layouts: [Arc<wgpu::BindGroupLayout>; 3],
+    target: Target,
+}
+
+/// Compiles shaders on a separate thread, so creating a big pipeline doesn't stall the ui.
+/// Uses flume because callback resources have to be `Sync`
+struct Background {
+    job_tx: flume::Sender<CompileJob>,
+    compiled_rx: flume::Receiver<Compiled>,
+}
 
 pub struct Compute {
-    pipeline: wgpu::ComputePipeline,
+    pipeline: Pipeline,
     compute_shader: wgpu::ShaderModule,
+    kind: ShaderKind,
+    meta: ShaderMeta,
+
+    target: wgpu::TextureView,
+    target_texture: Arc<wgpu::Texture>,
+    target_format: wgpu::TextureFormat,
+    target_read_write: bool,
+    /// Output format the pipeline is built for, the target follows it in `update_texture`
+    format: wgpu::TextureFormat,
+    output: OutputAccess,
+    /// Copy of the output from the last step, for `OutputAccess::Copy`
+    previous: Option<Texture>,
+    accumulation: Option<Accumulation>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
 
     data_bind_group: wgpu::BindGroup,
-    data_bind_group_layout: wgpu::BindGroupLayout,
+    data_bind_group_layout: Arc<wgpu::BindGroupLayout>,
     data_buffer: wgpu::Buffer,
     data: ComputeDataUniform,
+    params_buffer: wgpu::Buffer,
+    /// Last values passed to `set_params`, changing them starts a new accumulation
+    params: Vec<f32>,
 
     texture_bind_group: wgpu::BindGroup,
     texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// Takes the place of the output texture in fragment mode, which draws to it instead
+    empty_bind_group: wgpu::BindGroup,
+    empty_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+
+    /// Input textures, `channel0-3` in WGSL and `iChannel0-3` in GLSL
+    channels_bind_group: wgpu::BindGroup,
+    channels_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+
+    /// `@group(3)`, rebuilt with every shader
+    storage: Storage,
+
+    timer: Option<GpuTimer>,
+    background: Option<Background>,
 }
 
 impl Compute {
-    pub fn new(device: &wgpu::Device, texture: &Texture, shader_path: &str) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Texture,
+        shader_path: &str,
+    ) -> Self {
+        let shader = prelude::load_shader(shader_path).expect("Compute shader not found");
+        Self::from_shader(device, queue, texture, shader_path, shader)
+    }
+
+    /// Like `new`, with a shader that is already loaded
+    pub fn from_shader(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Texture,
+        shader_path: &str,
+        shader: Shader,
+    ) -> Self {
+        let (kind, meta) = (shader.kind, shader.meta.clone());
+        let (output, source) = OutputAccess::pick(shader, texture.format, texture.read_write);
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(
-                std::fs::read_to_string(shader_path)
-                    .expect("Compute shader not found")
-                    .into(),
-            ),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
         });
 
         let data = ComputeDataUniform {
@@ -32,77 +397,204 @@ impl Compute {
             height: texture.height,
 
             t: 0.,
+            frame: 0,
+            mouse: [0.; 4],
         };
         let data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: bytemuck::cast_slice(&[data]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let params_buffer = Self::create_params_buffer(device, &meta);
 
+        let uniform = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
         let data_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                        ty: uniform,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                        ty: uniform,
+                        count: None,
                     },
-                    count: None,
-                }],
+                ],
             });
-        let data_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &data_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: data_buffer.as_entire_binding(),
-            }],
-        });
+        let data_bind_group = Self::create_data_bind_group(
+            device,
+            &data_bind_group_layout,
+            &data_buffer,
+            &params_buffer,
+        );
 
+        let accumulation =
+            accumulates(kind, &meta).then(|| Accumulation::new(device, &texture.inner));
         let texture_bind_group_layout =
+            output.layout(device, texture.format, accumulation.is_some());
+        let previous =
+            (output == OutputAccess::Copy).then(|| Self::create_previous(device, &texture.inner));
+        let target = texture
+            .inner
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_bind_group = Self::create_texture_bind_group(
+            device,
+            &texture_bind_group_layout,
+            &target,
+            previous.as_ref(),
+            accumulation.as_ref(),
+        );
+        let empty_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: texture.storage_texture_binding_type(),
-                    count: None,
-                }],
+                entries: &[],
             });
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: &texture_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: texture.texture_binding_resource(),
-            }],
+            layout: &empty_bind_group_layout,
+            entries: &[],
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
         });
 
+        let channels_bind_group_layout = Self::create_channels_layout(device);
+        let channels_bind_group = Self::create_channels(
+            device,
+            queue,
+            &channels_bind_group_layout,
+            &meta.texture_paths(shader_path),
+        );
+
+        let storage = Storage::new(device, &meta, kind);
+
         let pipeline = Self::create_pipeline(
             device,
             &compute_shader,
-            &[&data_bind_group_layout, &texture_bind_group_layout],
+            kind,
+            &[
+                &data_bind_group_layout,
+                &texture_bind_group_layout,
+                &empty_bind_group_layout,
+                &channels_bind_group_layout,
+                &storage.layout,
+            ],
+            &meta.entry_points(),
+            texture.format,
         );
 
         Self {
             pipeline,
             compute_shader,
+            kind,
+            meta,
+
+            target,
+            target_texture: texture.inner.clone(),
+            target_format: texture.format,
+            format: texture.format,
+            target_read_write: texture.read_write,
+            output,
+            previous,
+            accumulation,
+            vertex_buffer,
+            index_buffer,
 
             data_bind_group,
-            data_bind_group_layout,
+            data_bind_group_layout: Arc::new(data_bind_group_layout),
             data_buffer,
             data,
+            params_buffer,
+            params: Vec::new(),
 
             texture_bind_group,
             texture_bind_group_layout,
+            empty_bind_group,
+            empty_bind_group_layout: Arc::new(empty_bind_group_layout),
+
+            channels_bind_group,
+            channels_bind_group_layout: Arc::new(channels_bind_group_layout),
+
+            storage,
+
+            timer: None,
+            background: None,
         }
     }
 
+    /// Moves loading and compiling shaders to a separate thread. `reload_shader` and
+    /// `set_shader` return right away and `poll_compiled` swaps in the result when it is ready
+    pub fn enable_background_compile(
+        &mut self,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+    ) {
+        let (job_tx, job_rx) = flume::unbounded();
+        let (compiled_tx, compiled_rx) = flume::unbounded();
+        std::thread::spawn(move || compile_thread(&device, &queue, job_rx, compiled_tx));
+        self.background = Some(Background {
+            job_tx,
+            compiled_rx,
+        });
+    }
+
+    pub fn enable_timing(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.timer = Some(GpuTimer::new(device, queue));
+    }
+
+    /// Latest gpu time of a step, if timing is enabled and a new measurement is available
+    pub fn poll_timing(&mut self) -> Option<GpuTime> {
+        self.timer.as_mut().and_then(|t| t.poll())
+    }
+
+    pub fn meta(&self) -> &ShaderMeta {
+        &self.meta
+    }
+
+    /// Entry point of each compute pass, empty for fragment shaders
+    pub fn entry_points(&self) -> Vec<String> {
+        if self.kind == ShaderKind::Fragment {
+            return Vec::new();
+        }
+        self.meta.entry_points()
+    }
+
+    /// Number of workgroups each compute pass dispatches per step, empty for fragment
+    /// shaders. Passes whose size fails to evaluate are skipped, see `dispatch_issues`
+    pub fn dispatch_sizes(&self) -> Vec<[u32; 3]> {
+        if self.kind == ShaderKind::Fragment {
+            return Vec::new();
+        }
+        self.meta
+            .passes()
+            .iter()
+            .map(|pass| {
+                self.meta
+                    .dispatch_size(pass, self.data.width, self.data.height)
+                    .unwrap_or([0; 3])
+            })
+            .collect()
+    }
+
     pub fn step(
-        &self,
+        &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         before_submit: Option<(
@@ -110,118 +602,756 @@ impl Compute {
             wgpu::ImageCopyBuffer,
             wgpu::Extent3d,
         )>,
-    ) {
+    ) -> wgpu::SubmissionIndex {
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        if let Some(accumulation) = &self.accumulation {
+            accumulation.write_sample(queue);
+        }
 
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
+        match &self.pipeline {
+            Pipeline::Compute(pipelines) => {
+                // Sizes from the toml can be anything, going over the limit is a validation
+                // error. `dispatch_issues` reports the passes cut down here
+                let max = device.limits().max_compute_workgroups_per_dimension;
+                let sizes = self.dispatch_sizes();
+                // A pass per entry point so each gets its own timestamps, later passes
+                // see the writes of earlier ones
+                for (i, (pipeline, size)) in pipelines.iter().zip(sizes).enumerate() {
+                    let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: None,
+                        timestamp_writes: self.timer.as_ref().and_then(|t| t.pass_writes(i)),
+                    });
 
-            cpass.set_pipeline(&self.pipeline);
-            cpass.set_bind_group(0, &self.data_bind_group, &[]);
-            cpass.set_bind_group(1, &self.texture_bind_group, &[]);
-            cpass.dispatch_workgroups(self.data.width, self.data.height, 1);
+                    cpass.set_bind_group(0, &self.data_bind_group, &[]);
+                    cpass.set_bind_group(1, &self.texture_bind_group, &[]);
+                    cpass.set_bind_group(2, &self.channels_bind_group, &[]);
+                    cpass.set_bind_group(3, &self.storage.bind_group, &[]);
+                    let [x, y, z] = size.map(|s| s.min(max));
+                    cpass.set_pipeline(pipeline);
+                    cpass.dispatch_workgroups(x, y, z);
+                }
+            }
+            Pipeline::Fragment(pipeline) => {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &self.target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: self.timer.as_ref().and_then(|t| t.render_pass_writes()),
+                    occlusion_query_set: None,
+                });
+
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, &self.data_bind_group, &[]);
+                rpass.set_bind_group(1, &self.empty_bind_group, &[]);
+                rpass.set_bind_group(2, &self.channels_bind_group, &[]);
+                rpass.set_bind_group(3, &self.storage.bind_group, &[]);
+                rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            }
+        }
+        if let Some(previous) = &self.previous {
+            Self::copy_to_previous(&mut encoder, &self.target_texture, previous);
+        }
+        let passes = match &self.pipeline {
+            Pipeline::Compute(pipelines) => pipelines.len(),
+            Pipeline::Fragment(_) => 1,
+        };
+        if let Some(timer) = self.timer.as_mut() {
+            timer.resolve(&mut encoder, passes);
         }
 
         if let Some((texture, buf, size)) = before_submit {
             encoder.copy_texture_to_buffer(texture, buf, size);
         }
 
-        queue.submit(Some(encoder.finish()));
+        let submission = queue.submit(Some(encoder.finish()));
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            accumulation.samples = accumulation.samples.saturating_add(1);
+        }
+        self.data.frame = self.data.frame.wrapping_add(1);
+        queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[self.data]));
+        if let Some(timer) = self.timer.as_mut() {
+            timer.after_submit(queue);
+        }
+        submission
     }
 
-    pub fn reload_shader(&mut self, device: &wgpu::Device, shader_path: &str) {
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
-        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(
-                std::fs::read_to_string(shader_path)
-                    .expect("Compute shader not found")
-                    .into(),
-            ),
-        });
+    /// Returns how long compiling the shader took, or `None` if it failed or is compiled
+    /// in the background
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_path: &str,
+    ) -> Option<Duration> {
+        if self.background.is_some() {
+            self.send_job(shader_path, None);
+            return None;
+        }
+        match prelude::load_shader(shader_path) {
+            Ok(shader) => self.set_shader(device, queue, shader_path, shader),
+            Err(e) => {
+                println!("Error in compute shader!!:{}", e);
+                None
+            }
+        }
+    }
 
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
-        let pipeline = Self::create_pipeline(
+    /// Swaps in `shader`, the current pipeline is kept when it doesn't compile
+    pub fn set_shader(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_path: &str,
+        shader: Shader,
+    ) -> Option<Duration> {
+        if self.background.is_some() {
+            self.send_job(shader_path, Some(shader));
+            return None;
+        }
+        let compiled = compile(
             device,
-            &compute_shader,
-            &[
+            queue,
+            shader_path,
+            shader,
+            [
                 &self.data_bind_group_layout,
-                &self.texture_bind_group_layout,
+                &self.empty_bind_group_layout,
+                &self.channels_bind_group_layout,
             ],
-        );
-        if let Some(e) = device.pop_error_scope().block_on() {
-            println!("Error in compute shader!!:{}", e);
-            return;
+            self.compile_target(),
+        )?;
+        Some(self.apply(device, queue, compiled))
+    }
+
+    /// Swaps in the newest shader finished by the compile thread
+    pub fn poll_compiled(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<Duration> {
+        let compiled = self.background.as_ref()?.compiled_rx.try_iter().last()?;
+        Some(self.apply(device, queue, compiled))
+    }
+
+    fn compile_target(&self) -> Target {
+        Target {
+            format: self.target_format,
+            read_write: self.target_read_write,
         }
-        self.compute_shader = compute_shader;
-        self.pipeline = pipeline;
     }
 
-    pub fn update_texture(&mut self, device: &wgpu::Device, texture: &Texture) {
-        self.texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: texture.storage_texture_binding_type(),
-                    count: None,
-                }],
-            });
-        self.texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.texture_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: texture.texture_binding_resource(),
-            }],
+    fn send_job(&self, path: &str, shader: Option<Shader>) {
+        let Some(background) = self.background.as_ref() else {
+            return;
+        };
+        let _ = background.job_tx.send(CompileJob {
+            path: path.into(),
+            shader,
+            layouts: [
+                self.data_bind_group_layout.clone(),
+                self.empty_bind_group_layout.clone(),
+                self.channels_bind_group_layout.clone(),
+            ],
+            target: self.compile_target(),
         });
+    }
+
+    fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        compiled: Compiled,
+    ) -> Duration {
+        self.pipeline = compiled.pipeline;
+        self.compute_shader = compiled.compute_shader;
+        self.kind = compiled.kind;
+        self.meta = compiled.meta;
+        self.data.frame = 0;
+
+        self.output = compiled.output;
+        if self.output != OutputAccess::Copy {
+            self.previous = None;
+        } else if self.previous.is_none() {
+            // Starts from the current output, like reading it directly would
+            let previous = Self::create_previous(device, &self.target_texture);
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            Self::copy_to_previous(&mut encoder, &self.target_texture, &previous);
+            queue.submit(Some(encoder.finish()));
+            self.previous = Some(previous);
+        }
+        self.accumulation = accumulates(self.kind, &self.meta)
+            .then(|| Accumulation::new(device, &self.target_texture));
+        self.texture_bind_group_layout = compiled.texture_bind_group_layout;
+        // A new format needs a new target first, which `update_texture` binds
+        if compiled.format == self.target_format {
+            self.texture_bind_group = Self::create_texture_bind_group(
+                device,
+                &self.texture_bind_group_layout,
+                &self.target,
+                self.previous.as_ref(),
+                self.accumulation.as_ref(),
+            );
+        }
+        self.format = compiled.format;
+        if self.meta.output.clear.contains(&ClearOn::Reload) {
+            self.clear_output(device, queue);
+        }
 
+        self.params_buffer = compiled.params_buffer;
+        self.data_bind_group = Self::create_data_bind_group(
+            device,
+            &self.data_bind_group_layout,
+            &self.data_buffer,
+            &self.params_buffer,
+        );
+        self.channels_bind_group = compiled.channels_bind_group;
+        self.storage = compiled.storage;
+        compiled.time
+    }
+
+    /// Puts the storage buffers back to their initial contents, clears the output if the
+    /// shader asks for it, drops the accumulated samples and starts counting frames from 0
+    /// again
+    pub fn reset(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.storage.reset(queue, &self.meta);
+        self.reset_accumulation();
+        if self.meta.output.clear.contains(&ClearOn::Reset) {
+            self.clear_output(device, queue);
+        }
+        self.data.frame = 0;
+        queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+
+    /// The pipeline only has to be rebuilt when the format changes, not on every resize.
+    /// Without `ClearOn::Resize` the new texture starts with as much of the old output as
+    /// fits
+    pub fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Texture,
+    ) {
+        let format_changed = texture.format != self.target_format;
+        let keep = !format_changed && !self.meta.output.clear.contains(&ClearOn::Resize);
+        let previous = self
+            .previous
+            .as_ref()
+            .map(|_| Self::create_previous(device, &texture.inner));
+        if keep {
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            copy_overlap(&mut encoder, &self.target_texture, &texture.inner);
+            if let (Some(old), Some(new)) = (&self.previous, &previous) {
+                copy_overlap(&mut encoder, &old.inner, &new.inner);
+            }
+            queue.submit(Some(encoder.finish()));
+        }
+        self.previous = previous;
+        if self.accumulation.is_some() {
+            self.accumulation = Some(Accumulation::new(device, &texture.inner));
+        }
+
+        if format_changed {
+            self.texture_bind_group_layout =
+                self.output
+                    .layout(device, texture.format, self.accumulation.is_some());
+        }
+        self.target = texture
+            .inner
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.target_texture = texture.inner.clone();
+        self.target_format = texture.format;
+        self.target_read_write = texture.read_write;
+        self.texture_bind_group = Self::create_texture_bind_group(
+            device,
+            &self.texture_bind_group_layout,
+            &self.target,
+            self.previous.as_ref(),
+            self.accumulation.as_ref(),
+        );
+
+        if !format_changed {
+            return;
+        }
         self.pipeline = Self::create_pipeline(
             device,
             &self.compute_shader,
+            self.kind,
             &[
                 &self.data_bind_group_layout,
                 &self.texture_bind_group_layout,
+                &self.empty_bind_group_layout,
+                &self.channels_bind_group_layout,
+                &self.storage.layout,
             ],
+            &self.meta.entry_points(),
+            self.target_format,
         );
     }
 
-    pub fn create_pipeline(
+    /// Sets the output and its copy to transparent black
+    fn clear_output(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let views = std::iter::once(&self.target).chain(self.previous.as_ref().map(|p| &p.view));
+        for view in views {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Texture for `OutputAccess::Copy` with the size and format of `target`
+    fn create_previous(device: &wgpu::Device, target: &wgpu::Texture) -> Texture {
+        Texture::new(
+            target.width(),
+            target.height(),
+            target.format(),
+            device,
+            wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        )
+    }
+
+    fn copy_to_previous(
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Texture,
+        previous: &Texture,
+    ) {
+        encoder.copy_texture_to_texture(
+            target.as_image_copy(),
+            previous.inner.as_image_copy(),
+            target.size(),
+        );
+    }
+
+    /// Output texture at binding 0, its copy at binding 1 if the shader reads one and the
+    /// accumulation at 2 and 3
+    fn create_texture_bind_group(
         device: &wgpu::Device,
-        shader: &wgpu::ShaderModule,
-        bind_group_layouts: &[&wgpu::BindGroupLayout],
-    ) -> wgpu::ComputePipeline {
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        layout: &wgpu::BindGroupLayout,
+        target: &wgpu::TextureView,
+        previous: Option<&Texture>,
+        accumulation: Option<&Accumulation>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(target),
+        }];
+        if let Some(previous) = previous {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: previous.texture_binding_resource(),
+            });
+        }
+        if let Some(accumulation) = accumulation {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: accumulation.sums.as_entire_binding(),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: accumulation.sample_buffer.as_entire_binding(),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Holds the values of the shader's params, at least 16 bytes so it can always be bound
+    fn create_params_buffer(device: &wgpu::Device, meta: &ShaderMeta) -> wgpu::Buffer {
+        let mut values = meta.default_params();
+        values.resize(values.len().next_multiple_of(4).max(4), 0.);
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&values),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    fn create_data_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        data_buffer: &wgpu::Buffer,
+        params_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            bind_group_layouts,
-            push_constant_ranges: &[],
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: data_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_channels_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let visibility = wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT;
+        let mut entries: Vec<_> = (0..CHANNELS)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            })
+            .collect();
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: CHANNELS,
+            visibility,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
         });
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        })
+    }
+
+    /// Loads the textures of a shader, channels without one get a black pixel
+    fn create_channels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        paths: &[PathBuf],
+    ) -> wgpu::BindGroup {
+        let black = image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 0, 255]));
+        let textures: Vec<_> = (0..CHANNELS as usize)
+            .map(|i| {
+                let image = paths.get(i).and_then(|path| match image::open(path) {
+                    Ok(image) => Some(image.to_rgba8()),
+                    Err(e) => {
+                        println!("Failed to load {}: {}", path.display(), e);
+                        None
+                    }
+                });
+                Texture::from_image(device, queue, image.as_ref().unwrap_or(&black))
+            })
+            .collect();
 
-        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        let mut entries: Vec<_> = textures
+            .iter()
+            .enumerate()
+            .map(|(binding, texture)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: texture.texture_binding_resource(),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: CHANNELS,
+            resource: textures[0].sampler_binding_resource(),
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
-            module: shader,
-            entry_point: "main",
+            layout,
+            entries: &entries,
         })
     }
 
+    /// `bind_group_layouts` are data, output texture, empty, channels and storage. Compute
+    /// shaders write to the output texture, fragment shaders get the empty group in its place
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        kind: ShaderKind,
+        bind_group_layouts: &[&wgpu::BindGroupLayout; 5],
+        entry_points: &[String],
+        format: wgpu::TextureFormat,
+    ) -> Pipeline {
+        let [data, texture, empty, channels, storage] = *bind_group_layouts;
+        match kind {
+            ShaderKind::Compute => {
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[data, texture, channels, storage],
+                        push_constant_ranges: &[],
+                    });
+
+                Pipeline::Compute(
+                    entry_points
+                        .iter()
+                        .map(|entry_point| {
+                            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                                label: Some(entry_point.as_str()),
+                                layout: Some(&pipeline_layout),
+                                module: shader,
+                                entry_point,
+                            })
+                        })
+                        .collect(),
+                )
+            }
+            ShaderKind::Fragment => {
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[data, empty, channels, storage],
+                        push_constant_ranges: &[],
+                    });
+
+                Pipeline::Fragment(
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: None,
+                        layout: Some(&pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: shader,
+                            entry_point: "plix_vs",
+                            buffers: &[Vertex::desc()],
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: shader,
+                            entry_point: "plix_fs",
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format,
+                                blend: None,
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                    }),
+                )
+            }
+        }
+    }
+
     pub fn update_texture_size(&mut self, queue: &wgpu::Queue, texture_dim: [u32; 2]) {
         self.data.width = texture_dim[0];
         self.data.height = texture_dim[1];
         queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[self.data]));
     }
 
+    /// Starts a new average when `t` changes, unless the shader is time independent
     pub fn update_time(&mut self, queue: &wgpu::Queue, t: f32) {
+        if t != self.data.t && !self.meta.output.time_independent {
+            self.reset_accumulation();
+        }
         self.data.t = t;
         queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[self.data]));
     }
+
+    /// Mouse input of the canvas, see `ComputeDataUniform::mouse`
+    pub fn update_mouse(&mut self, queue: &wgpu::Queue, mouse: [f32; 4]) {
+        if mouse != self.data.mouse {
+            self.reset_accumulation();
+        }
+        self.data.mouse = mouse;
+        queue.write_buffer(&self.data_buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+
+    /// Values of the `params` uniform, in the order of the shader's toml
+    pub fn set_params(&mut self, queue: &wgpu::Queue, values: &[f32]) {
+        if values != self.params {
+            self.reset_accumulation();
+            self.params = values.to_vec();
+        }
+        let len = values.len().min(self.params_buffer.size() as usize / 4);
+        if len > 0 {
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&values[..len]));
+        }
+    }
+
+    /// Format the output texture needs for the current shader. When it changes, the owner
+    /// of the texture recreates it and passes it to `update_texture` before the next step
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Number of samples in the output of a shader that accumulates
+    pub fn samples(&self) -> Option<u32> {
+        self.accumulation.as_ref().map(|a| a.samples)
+    }
+
+    /// Makes the next step start a new average
+    fn reset_accumulation(&mut self) {
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            accumulation.samples = 0;
+        }
+    }
+}
+
+/// Builds the pipeline and the shader's resources, `None` if the shader doesn't compile
+fn compile(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    shader_path: &str,
+    shader: Shader,
+    layouts: [&wgpu::BindGroupLayout; 3],
+    target: Target,
+) -> Option<Compiled> {
+    let start = Instant::now();
+    // Naga finds most problems without touching the device, which other threads share
+    if let Err(e) = reflect::check_pipeline(&shader) {
+        println!("Error in compute shader!!:{}", e);
+        return None;
+    }
+    let (kind, meta) = (shader.kind, shader.meta.clone());
+    let format = meta.format().texture_format();
+    let read_write = target.read_write && format == target.format;
+    let (output, source) = OutputAccess::pick(shader, format, read_write);
+    let [data, empty, channels] = layouts;
+    let created = gpu::with_error_scope(device, || {
+        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let storage = Storage::new(device, &meta, kind);
+        let texture_bind_group_layout = output.layout(device, format, accumulates(kind, &meta));
+        let pipeline = Compute::create_pipeline(
+            device,
+            &compute_shader,
+            kind,
+            &[
+                data,
+                &texture_bind_group_layout,
+                empty,
+                channels,
+                &storage.layout,
+            ],
+            &meta.entry_points(),
+            format,
+        );
+        (compute_shader, storage, texture_bind_group_layout, pipeline)
+    });
+    let (compute_shader, storage, texture_bind_group_layout, pipeline) = match created {
+        Ok(created) => created,
+        Err(e) => {
+            println!("Error in compute shader!!:{}", e);
+            return None;
+        }
+    };
+    let time = start.elapsed();
+
+    Some(Compiled {
+        pipeline,
+        compute_shader,
+        format,
+        kind,
+        params_buffer: Compute::create_params_buffer(device, &meta),
+        channels_bind_group: Compute::create_channels(
+            device,
+            queue,
+            channels,
+            &meta.texture_paths(shader_path),
+        ),
+        meta,
+        output,
+        texture_bind_group_layout,
+        storage,
+        time,
+    })
+}
+
+/// Copies the top left corner that both textures have in common
+fn copy_overlap(encoder: &mut wgpu::CommandEncoder, from: &wgpu::Texture, to: &wgpu::Texture) {
+    let size = wgpu::Extent3d {
+        width: from.width().min(to.width()),
+        height: from.height().min(to.height()),
+        depth_or_array_layers: 1,
+    };
+    encoder.copy_texture_to_texture(from.as_image_copy(), to.as_image_copy(), size);
 }
 
+fn compile_thread(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    job_rx: flume::Receiver<CompileJob>,
+    compiled_tx: flume::Sender<Compiled>,
+) {
+    while let Ok(job) = job_rx.recv() {
+        // Only the newest shader matters when several changes queued up
+        let job = job_rx.try_iter().last().unwrap_or(job);
+        let shader = match job.shader {
+            Some(shader) => shader,
+            None => match prelude::load_shader(&job.path) {
+                Ok(shader) => shader,
+                Err(e) => {
+                    println!("Error in compute shader!!:{}", e);
+                    continue;
+                }
+            },
+        };
+        let [data, empty, channels] = &job.layouts;
+        let layouts = [&**data, &**empty, &**channels];
+        let Some(compiled) = compile(device, queue, &job.path, shader, layouts, job.target) else {
+            continue;
+        };
+        if compiled_tx.send(compiled).is_err() {
+            return;
+        }
+    }
+}
+
+/// Fields of `ComputeDataUniform` as shaders declare them, with their byte offset and
+/// whether shaders have to declare them
+pub const DATA_FIELDS: &[(&str, &str, u32, bool)] = &[
+    (
+        "width",
+        "u32",
+        offset_of!(ComputeDataUniform, width) as u32,
+        true,
+    ),
+    (
+        "height",
+        "u32",
+        offset_of!(ComputeDataUniform, height) as u32,
+        true,
+    ),
+    ("t", "f32", offset_of!(ComputeDataUniform, t) as u32, true),
+    (
+        "frame",
+        "u32",
+        offset_of!(ComputeDataUniform, frame) as u32,
+        false,
+    ),
+    (
+        "mouse",
+        "vec4<f32>",
+        offset_of!(ComputeDataUniform, mouse) as u32,
+        false,
+    ),
+];
+
 #[repr(C)]
 #[derive(Pod, Zeroable, Clone, Copy, Debug)]
 struct ComputeDataUniform {
@@ -229,4 +1359,48 @@ struct ComputeDataUniform {
     height: u32,
 
     t: f32,
+    /// Number of steps since the shader was loaded
+    frame: u32,
+    /// Pixel under the pointer while the primary button is down in xy, pixel where it went
+    /// down in zw, with z negative once it is released. Like Shadertoy's `iMouse`
+    mouse: [f32; 4],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulation_fits_one_storage_buffer() {
+        let limits = wgpu::Limits {
+            max_storage_buffer_binding_size: 128 << 20,
+            ..wgpu::Limits::default()
+        };
+        assert_eq!(max_accumulation_size(&limits), 128 << 20);
+        assert!(accumulation_issue(&limits, 2048, 2048).is_none());
+
+        let issue = accumulation_issue(&limits, 4096, 4096).unwrap();
+        assert!(issue.contains("256 MB"), "{}", issue);
+        assert!(issue.contains("first 2048 rows"), "{}", issue);
+
+        let small = wgpu::Limits {
+            max_buffer_size: 1000,
+            ..limits
+        };
+        assert_eq!(max_accumulation_size(&small), 992);
+    }
+
+    #[test]
+    fn oversize_dispatches_are_issues() {
+        let meta: ShaderMeta = toml::from_str(
+            "[constants]\nN = 8388608\n\n[[pass]]\nentry_point = \"simulate\"\n\
+             dispatch = [\"N / 64\"]\n\n[[pass]]\nentry_point = \"draw\"",
+        )
+        .unwrap();
+        let limits = wgpu::Limits::default();
+        let issues = dispatch_issues(ShaderKind::Compute, &meta, &limits, 640, 480);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert!(issues[0].starts_with("Pass `simulate` dispatches 131072 x 1 x 1"));
+        assert!(dispatch_issues(ShaderKind::Fragment, &meta, &limits, 640, 480).is_empty());
+    }
 }
@@ -0,0 +1,197 @@
+//! Integer expressions for the dispatch size of a pass, like `N / 64` or `width / 8`
+
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(u32),
+    Variable(String),
+    Op(Box<Node>, char, Box<Node>),
+}
+
+/// Expression from the toml with `+ - * /`, parentheses, numbers and variables.
+/// `/` rounds up, so a dispatch always covers every item
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+impl TryFrom<String> for Expr {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        let tokens = tokenize(&source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.sum()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected `{}` in `{}`", token, source));
+        }
+        Ok(Self { source, node })
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    pub fn variables(&self) -> Vec<&str> {
+        fn collect<'a>(node: &'a Node, out: &mut Vec<&'a str>) {
+            match node {
+                Node::Number(_) => {}
+                Node::Variable(name) => out.push(name),
+                Node::Op(a, _, b) => {
+                    collect(a, out);
+                    collect(b, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        collect(&self.node, &mut out);
+        out
+    }
+
+    /// Fails if a variable is unknown or something is divided by zero. Results
+    /// saturate instead of overflowing
+    pub fn eval(&self, variable: &impl Fn(&str) -> Option<u32>) -> Result<u32, String> {
+        fn eval(node: &Node, variable: &impl Fn(&str) -> Option<u32>) -> Result<u32, String> {
+            match node {
+                Node::Number(n) => Ok(*n),
+                Node::Variable(name) => variable(name).ok_or(format!("Unknown `{}`", name)),
+                Node::Op(a, op, b) => {
+                    let (a, b) = (eval(a, variable)?, eval(b, variable)?);
+                    match op {
+                        '+' => Ok(a.saturating_add(b)),
+                        '-' => Ok(a.saturating_sub(b)),
+                        '*' => Ok(a.saturating_mul(b)),
+                        _ if b == 0 => Err("Division by zero".into()),
+                        _ => Ok(a.div_ceil(b)),
+                    }
+                }
+            }
+        }
+        eval(&self.node, variable)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut token = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+            {
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            return Err(format!("Unexpected `{}` in `{}`", c, source));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_if(&mut self, ops: &str) -> Option<char> {
+        let token = self.tokens.get(self.pos)?;
+        let op = token
+            .chars()
+            .next()
+            .filter(|c| token.len() == 1 && ops.contains(*c))?;
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(op) = self.next_if("+-") {
+            node = Node::Op(Box::new(node), op, Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.value()?;
+        while let Some(op) = self.next_if("*/") {
+            node = Node::Op(Box::new(node), op, Box::new(self.value()?));
+        }
+        Ok(node)
+    }
+
+    fn value(&mut self) -> Result<Node, String> {
+        if self.next_if("(").is_some() {
+            let node = self.sum()?;
+            return match self.next_if(")") {
+                Some(_) => Ok(node),
+                None => Err("Missing `)`".into()),
+            };
+        }
+        let Some(token) = self.tokens.get(self.pos) else {
+            return Err("Expression ends early".into());
+        };
+        let node = if token.starts_with(|c: char| c.is_ascii_digit()) {
+            Node::Number(
+                token
+                    .parse()
+                    .map_err(|_| format!("Invalid number `{}`", token))?,
+            )
+        } else if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            Node::Variable(token.clone())
+        } else {
+            return Err(format!("Unexpected `{}`", token));
+        };
+        self.pos += 1;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Result<u32, String> {
+        let expr = Expr::try_from(source.to_owned()).unwrap();
+        expr.eval(&|name| match name {
+            "N" => Some(1000),
+            "width" => Some(100),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn follows_precedence_and_rounds_up() {
+        assert_eq!(eval("N / 64"), Ok(16));
+        assert_eq!(eval("width / 8 + 1"), Ok(14));
+        assert_eq!(eval("2 * (3 + 4)"), Ok(14));
+        assert_eq!(eval("1 - 5"), Ok(0));
+        assert_eq!(eval("N / (width - 100)"), Err("Division by zero".into()));
+        assert_eq!(eval("height"), Err("Unknown `height`".into()));
+    }
+
+    #[test]
+    fn invalid_expressions_are_errors() {
+        for source in ["", "N /", "(N", "N 64", "N % 2", "99999999999"] {
+            assert!(Expr::try_from(source.to_owned()).is_err(), "{}", source);
+        }
+        let expr = Expr::try_from("(N + M) / width".to_owned()).unwrap();
+        assert_eq!(expr.variables(), ["N", "M", "width"]);
+    }
+}
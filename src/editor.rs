@@ -0,0 +1,491 @@
+use eframe::egui::{self, text::LayoutJob, Color32, FontId, TextFormat};
+use std::{collections::BTreeMap, ops::Range, path::Path};
+
+use crate::{reflect::Diagnostic, shadertoy};
+
+const KEYWORDS: &[&str] = &[
+    "alias",
+    "break",
+    "case",
+    "const",
+    "const_assert",
+    "continue",
+    "continuing",
+    "default",
+    "diagnostic",
+    "discard",
+    "else",
+    "enable",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "let",
+    "loop",
+    "override",
+    "requires",
+    "return",
+    "struct",
+    "switch",
+    "true",
+    "var",
+    "while",
+];
+const TYPES: &[&str] = &[
+    "array",
+    "atomic",
+    "bool",
+    "f16",
+    "f32",
+    "i32",
+    "ptr",
+    "sampler",
+    "sampler_comparison",
+    "u32",
+    "uniform",
+    "storage",
+    "function",
+    "private",
+    "workgroup",
+    "read",
+    "write",
+    "read_write",
+];
+const TYPE_PREFIXES: &[&str] = &["vec", "mat", "texture_"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Comment,
+    Keyword,
+    Type,
+    Attribute,
+    Number,
+    Ident,
+    Punct,
+    Whitespace,
+}
+
+/// Splits WGSL source into highlighted ranges, every byte ends up in exactly one range
+fn tokenize(text: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let bytes = text.as_bytes();
+    let word_end = |start: usize| {
+        start
+            + text[start..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(text.len() - start)
+    };
+
+    let mut i = 0;
+    while i < text.len() {
+        let c = text[i..].chars().next().unwrap();
+        let next = bytes.get(i + 1).copied();
+        let (token, end) = if text[i..].starts_with("//") {
+            (
+                Token::Comment,
+                text[i..].find('\n').map_or(text.len(), |e| i + e),
+            )
+        } else if text[i..].starts_with("/*") {
+            (
+                Token::Comment,
+                text[i + 2..].find("*/").map_or(text.len(), |e| i + e + 4),
+            )
+        } else if c.is_whitespace() {
+            let len = text[i..].find(|c: char| !c.is_whitespace());
+            (Token::Whitespace, len.map_or(text.len(), |e| i + e))
+        } else if c == '@' {
+            (Token::Attribute, word_end(i + 1))
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) {
+            let len = text[i..].find(|c: char| !(c.is_alphanumeric() || c == '.'));
+            (Token::Number, len.map_or(text.len(), |e| i + e))
+        } else if c.is_alphabetic() || c == '_' {
+            let end = word_end(i);
+            let word = &text[i..end];
+            let token = if KEYWORDS.contains(&word) {
+                Token::Keyword
+            } else if TYPES.contains(&word) || TYPE_PREFIXES.iter().any(|p| word.starts_with(p)) {
+                Token::Type
+            } else {
+                Token::Ident
+            };
+            (token, end)
+        } else {
+            (Token::Punct, i + c.len_utf8())
+        };
+        tokens.push((token, i..end));
+        i = end;
+    }
+    tokens
+}
+
+fn color(token: Token, visuals: &egui::Visuals) -> Color32 {
+    let dark = visuals.dark_mode;
+    match token {
+        Token::Comment => visuals.weak_text_color(),
+        Token::Keyword if dark => Color32::from_rgb(198, 120, 221),
+        Token::Keyword => Color32::from_rgb(140, 40, 170),
+        Token::Type if dark => Color32::from_rgb(86, 182, 194),
+        Token::Type => Color32::from_rgb(20, 120, 130),
+        Token::Attribute if dark => Color32::from_rgb(229, 192, 123),
+        Token::Attribute => Color32::from_rgb(160, 100, 0),
+        Token::Number if dark => Color32::from_rgb(152, 195, 121),
+        Token::Number => Color32::from_rgb(60, 130, 30),
+        Token::Ident | Token::Punct | Token::Whitespace => visuals.text_color(),
+    }
+}
+
+/// Colors the source and marks the background of lines with errors
+fn highlight(text: &str, visuals: &egui::Visuals, error_lines: &[usize]) -> LayoutJob {
+    let font_id = FontId::monospace(12.);
+    let error_background = visuals.error_fg_color.gamma_multiply(0.25);
+    let mut job = LayoutJob::default();
+    let mut line = 1;
+    for (token, range) in tokenize(text) {
+        // Whitespace is split at line breaks so a marked line doesn't spill into the next
+        let mut start = range.start;
+        while start < range.end {
+            let end = match token {
+                Token::Whitespace | Token::Comment => text[start..range.end]
+                    .find('\n')
+                    .map_or(range.end, |e| start + e + 1),
+                _ => range.end,
+            };
+            let background = if error_lines.contains(&line) {
+                error_background
+            } else {
+                Color32::TRANSPARENT
+            };
+            job.append(
+                &text[start..end],
+                0.,
+                TextFormat {
+                    font_id: font_id.clone(),
+                    color: color(token, visuals),
+                    background,
+                    ..Default::default()
+                },
+            );
+            line += text[start..end].matches('\n').count();
+            start = end;
+        }
+    }
+    job
+}
+
+/// Source being edited and what was last read from or written to disk
+struct Buffer {
+    text: String,
+    saved: String,
+    /// The file changed on disk while there were unsaved edits
+    conflict: bool,
+}
+
+impl Buffer {
+    fn new(text: String) -> Self {
+        Self {
+            saved: text.clone(),
+            text,
+            conflict: false,
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.text != self.saved
+    }
+
+    /// Takes the disk version unless it would throw away unsaved edits
+    fn sync(&mut self, disk: String) {
+        if disk == self.saved {
+            return;
+        }
+        if self.is_dirty() {
+            self.conflict = disk != self.text;
+            if !self.conflict {
+                self.saved = disk;
+            }
+        } else {
+            self.text = disk.clone();
+            self.saved = disk;
+        }
+    }
+}
+
+/// Code editor for the selected shader. Saving writes the file and the watcher in
+/// `ShaderManager` reloads it like any other change
+pub struct Editor {
+    pub visible: bool,
+    path: String,
+    /// Unsaved edits are kept when switching to another shader
+    buffers: BTreeMap<String, Buffer>,
+    error: Option<String>,
+    /// The text of the shown buffer changed since the last `take_edit`
+    edited: bool,
+}
+
+impl Editor {
+    pub fn new(visible: bool) -> Self {
+        Self {
+            visible,
+            path: String::new(),
+            buffers: BTreeMap::new(),
+            error: None,
+            edited: false,
+        }
+    }
+
+    /// Path and text of the shown buffer when it was edited since the last call
+    pub fn take_edit(&mut self) -> Option<(String, String)> {
+        if !std::mem::take(&mut self.edited) {
+            return None;
+        }
+        let buffer = self.buffers.get(&self.path)?;
+        Some((self.path.clone(), buffer.text.clone()))
+    }
+
+    /// Edits of `path` that are not saved yet
+    pub fn unsaved_text(&self, path: &str) -> Option<&str> {
+        self.buffers
+            .get(path)
+            .filter(|b| b.is_dirty())
+            .map(|b| b.text.as_str())
+    }
+
+    /// Shows `path`, called whenever the shader is selected or changes on disk
+    pub fn open(&mut self, path: &str) {
+        self.path = path.into();
+        self.error = None;
+        if shadertoy::is_project(Path::new(path)) {
+            return;
+        }
+        match std::fs::read_to_string(path) {
+            Ok(disk) => match self.buffers.get_mut(path) {
+                Some(buffer) => buffer.sync(disk),
+                None => {
+                    self.buffers.insert(path.into(), Buffer::new(disk));
+                }
+            },
+            Err(e) => self.error = Some(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Refuses to write when the file was changed by something else since it was loaded
+    fn save(&mut self, force: bool) {
+        let Some(buffer) = self.buffers.get_mut(&self.path) else {
+            return;
+        };
+        if !force {
+            match std::fs::read_to_string(&self.path) {
+                Ok(disk) if disk != buffer.saved => {
+                    buffer.conflict = true;
+                    return;
+                }
+                Err(e) => {
+                    self.error = Some(format!("{}: {}", self.path, e));
+                    return;
+                }
+                _ => {}
+            }
+        }
+        match std::fs::write(&self.path, &buffer.text) {
+            Ok(_) => {
+                buffer.saved = buffer.text.clone();
+                buffer.conflict = false;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("{}: {}", self.path, e)),
+        }
+    }
+
+    fn reload(&mut self) {
+        if let Some(buffer) = self.buffers.get_mut(&self.path) {
+            match std::fs::read_to_string(&self.path) {
+                Ok(disk) => {
+                    *buffer = Buffer::new(disk);
+                    self.edited = true;
+                }
+                Err(e) => self.error = Some(format!("{}: {}", self.path, e)),
+            }
+        }
+    }
+
+    /// `diagnostics` are the errors of the saved version of the selected shader
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, diagnostics: &[Diagnostic]) {
+        let Some(buffer) = self.buffers.get_mut(&self.path) else {
+            ui.label(format!("{} can't be edited here", self.path));
+            if let Some(e) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+            return;
+        };
+
+        let mut save = false;
+        let mut force = false;
+        let mut reload = false;
+        ui.horizontal(|ui| {
+            let dirty = if buffer.is_dirty() { " (unsaved)" } else { "" };
+            ui.label(egui::RichText::new(format!("{}{}", self.path, dirty)).monospace());
+            save = ui
+                .add_enabled(buffer.is_dirty(), egui::Button::new("Save"))
+                .on_hover_text("Ctrl+S")
+                .clicked();
+            if ui
+                .add_enabled(buffer.is_dirty(), egui::Button::new("Revert"))
+                .clicked()
+            {
+                reload = true;
+            }
+        });
+        if buffer.conflict {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "The file changed on disk while there were unsaved edits",
+            );
+            ui.horizontal(|ui| {
+                reload = ui.button("Load from disk").clicked();
+                force = ui.button("Overwrite").clicked();
+            });
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+
+        let line_count = buffer.text.lines().count().max(1);
+        let error_lines: Vec<usize> = diagnostics
+            .iter()
+            .filter_map(|d| d.line)
+            .filter(|l| *l <= line_count)
+            .collect();
+        for diagnostic in diagnostics {
+            let text = match diagnostic.line {
+                Some(line) if line <= line_count => format!("{}: {}", line, diagnostic.message),
+                Some(_) => format!("prelude: {}", diagnostic.message),
+                None => diagnostic.message.clone(),
+            };
+            ui.label(
+                egui::RichText::new(text)
+                    .monospace()
+                    .color(ui.visuals().error_fg_color),
+            );
+        }
+
+        let mut layouter = |ui: &egui::Ui, text: &str, _wrap_width: f32| {
+            let job = highlight(text, ui.visuals(), &error_lines);
+            ui.fonts(|f| f.layout_job(job))
+        };
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                ui.horizontal_top(|ui| {
+                    let mut gutter = LayoutJob::default();
+                    for line in 1..=line_count {
+                        let color = if error_lines.contains(&line) {
+                            ui.visuals().error_fg_color
+                        } else {
+                            ui.visuals().weak_text_color()
+                        };
+                        gutter.append(
+                            &format!("{:>4}\n", line),
+                            0.,
+                            TextFormat::simple(FontId::monospace(12.), color),
+                        );
+                    }
+                    ui.add_space(4.);
+                    ui.label(gutter);
+
+                    let response = ui.add(
+                        egui::TextEdit::multiline(&mut buffer.text)
+                            .code_editor()
+                            .desired_width(f32::INFINITY)
+                            .desired_rows(line_count)
+                            .layouter(&mut layouter),
+                    );
+                    let shortcut =
+                        egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+                    self.edited |= response.changed();
+                    if response.has_focus() && ui.input_mut(|i| i.consume_shortcut(&shortcut)) {
+                        save = true;
+                    }
+                });
+            });
+
+        if reload {
+            self.reload();
+        } else if save || force {
+            self.save(force);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(Token, &str)> {
+        tokenize(text)
+            .into_iter()
+            .filter(|(t, _)| *t != Token::Whitespace)
+            .map(|(t, r)| (t, &text[r]))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_cover_the_source() {
+        let text = "@compute @workgroup_size(8, 8)\nfn main() { /* a */ let x = 1.5e3; } // end";
+        let tokens = tokenize(text);
+        let mut end = 0;
+        for (_, range) in tokens.iter() {
+            assert_eq!(range.start, end);
+            end = range.end;
+        }
+        assert_eq!(end, text.len());
+    }
+
+    #[test]
+    fn classifies_tokens() {
+        assert_eq!(
+            kinds("@group(0) var<uniform> data: vec2<f32>; // x"),
+            [
+                (Token::Attribute, "@group"),
+                (Token::Punct, "("),
+                (Token::Number, "0"),
+                (Token::Punct, ")"),
+                (Token::Keyword, "var"),
+                (Token::Punct, "<"),
+                (Token::Type, "uniform"),
+                (Token::Punct, ">"),
+                (Token::Ident, "data"),
+                (Token::Punct, ":"),
+                (Token::Type, "vec2"),
+                (Token::Punct, "<"),
+                (Token::Type, "f32"),
+                (Token::Punct, ">"),
+                (Token::Punct, ";"),
+                (Token::Comment, "// x"),
+            ]
+        );
+        assert_eq!(
+            kinds(".5 /* a\nb */"),
+            [(Token::Number, ".5"), (Token::Comment, "/* a\nb */")]
+        );
+    }
+
+    #[test]
+    fn unsaved_edits_survive_external_changes() {
+        let mut buffer = Buffer::new("a".into());
+        buffer.sync("b".into());
+        assert_eq!(buffer.text, "b");
+        assert!(!buffer.is_dirty());
+
+        buffer.text = "mine".into();
+        buffer.sync("theirs".into());
+        assert_eq!(buffer.text, "mine");
+        assert!(buffer.conflict);
+
+        // Our own save coming back from the watcher is not a conflict
+        let mut buffer = Buffer::new("a".into());
+        buffer.text = "b".into();
+        buffer.sync("b".into());
+        assert!(!buffer.conflict);
+        assert!(!buffer.is_dirty());
+    }
+}
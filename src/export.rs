@@ -1,30 +1,90 @@
 use eframe::{
     egui::{self, containers, widgets},
-    wgpu,
+    egui_wgpu, wgpu,
 };
 use pollster::FutureExt;
-use std::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use crate::{compute::Compute, texture::Texture};
+use crate::{
+    compute::{self, Compute},
+    gpu::Gpu,
+    prelude, reflect,
+    shader_meta::ShaderMeta,
+    texture::Texture,
+};
 
-#[derive(Clone)]
-struct ExportData {
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ExportData {
     width: u32,
     height: u32,
     duration: f32,
+    /// Steps averaged into every frame of shaders that accumulate
+    samples: u32,
 
     shader: String,
+    /// Values of the shader's params by name, missing ones use the default
+    params: BTreeMap<String, f32>,
+    output: Option<String>,
+    /// Keeps the png frames in a folder instead of encoding a video
+    image_sequence: bool,
+    /// Renders on a separate device created from this adapter instead of the preview one
+    adapter: Option<String>,
+}
+
+impl Default for ExportData {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 800,
+            duration: 5.,
+            samples: 64,
+
+            shader: String::new(),
+            params: BTreeMap::new(),
+            output: None,
+            image_sequence: false,
+            adapter: None,
+        }
+    }
+}
+
+/// A list of exports rendered one after another by `--batch`
+#[derive(Debug, Deserialize)]
+struct BatchFile {
+    #[serde(default)]
+    job: Vec<ExportData>,
 }
 
 pub struct Export {
     data: ExportData,
     state: State,
+    adapters: Vec<String>,
+
+    jobs: Vec<Job>,
+    next_job_id: usize,
 
     start_export: mpsc::Sender<ExportData>,
     export_com: mpsc::Receiver<Msg>,
+    /// Shared with the export thread, kept to start a new one if it stops
+    gpu: Gpu,
 
-    thread_msg: String,
+    cancel: Arc<AtomicBool>,
     _thread: std::thread::JoinHandle<()>,
+
+    screenshot_requested: bool,
+    screenshot_tx: mpsc::Sender<String>,
+    screenshot_rx: mpsc::Receiver<String>,
+    screenshot_msg: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -33,62 +93,239 @@ enum State {
     Generating,
 }
 
+struct Job {
+    id: usize,
+    data: ExportData,
+    status: JobStatus,
+    adapter: Option<String>,
+}
+
+enum JobStatus {
+    Queued,
+    Running(Option<Progress>),
+    Finished(Outcome),
+}
+
 enum Msg {
-    Info(String),
-    Done,
+    Adapter(String),
+    Progress(Progress),
+    Done(Outcome),
 }
 
-impl Export {
-    pub fn new(shader: String) -> Self {
-        let (start_tx, start_rx) = mpsc::channel();
-        let (com_tx, com_rx) = mpsc::channel();
+enum Outcome {
+    Saved(String),
+    Failed(String),
+    Cancelled,
+}
 
-        let thread = std::thread::spawn(move || export_thread(start_rx, com_tx));
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    Initializing,
+    Rendering,
+    Encoding,
+}
 
-        Self {
-            data: ExportData {
-                width: 800,
-                height: 800,
-                duration: 5.,
+#[derive(Clone, Copy, Debug)]
+struct Progress {
+    stage: Stage,
+    frames_done: usize,
+    frames_total: usize,
+    elapsed: Duration,
+}
+
+impl Progress {
+    fn fraction(&self) -> f32 {
+        match self.stage {
+            Stage::Initializing => 0.,
+            Stage::Rendering if self.frames_total > 0 => {
+                self.frames_done as f32 / self.frames_total as f32
+            }
+            _ => 1.,
+        }
+    }
+
+    /// Estimated time left for rendering, based on the average time per frame so far
+    fn eta(&self) -> Option<Duration> {
+        if self.stage != Stage::Rendering || self.frames_done == 0 {
+            return None;
+        }
+        let per_frame = self.elapsed / self.frames_done as u32;
+        Some(per_frame * (self.frames_total - self.frames_done) as u32)
+    }
 
-                shader,
-            },
+    fn stage_name(&self) -> &'static str {
+        match self.stage {
+            Stage::Initializing => "Initializing",
+            Stage::Rendering => "Rendering",
+            Stage::Encoding => "Encoding video",
+        }
+    }
+}
+
+impl Export {
+    pub fn new(
+        data: ExportData,
+        shader: String,
+        meta: &ShaderMeta,
+        gpu: Gpu,
+        adapters: Vec<String>,
+    ) -> Self {
+        let (screenshot_tx, screenshot_rx) = mpsc::channel();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (start_tx, com_rx, thread) = spawn_export_thread(&gpu, &cancel);
+
+        let mut export = Self {
+            data,
             state: State::Waiting,
+            adapters,
+
+            jobs: Vec::new(),
+            next_job_id: 1,
 
             start_export: start_tx,
             export_com: com_rx,
+            gpu,
             _thread: thread,
 
-            thread_msg: String::new(),
-        }
+            cancel,
+
+            screenshot_requested: false,
+            screenshot_tx,
+            screenshot_rx,
+            screenshot_msg: String::new(),
+        };
+        export.set_shader(shader, meta);
+        export
     }
 
-    pub fn set_shader(&mut self, shader: String) {
+    /// Takes the export settings from the shader's toml when a different shader is selected
+    pub fn set_shader(&mut self, shader: String, meta: &ShaderMeta) {
+        if shader == self.data.shader {
+            return;
+        }
         self.data.shader = shader;
+        self.data.width = meta.width.unwrap_or(self.data.width);
+        self.data.height = meta.height.unwrap_or(self.data.height);
+        self.data.duration = meta.duration.unwrap_or(self.data.duration);
+        self.data.samples = meta.samples.unwrap_or(self.data.samples);
+    }
+
+    /// Settings used for the next job, saved between runs
+    pub fn data(&self) -> &ExportData {
+        &self.data
+    }
+
+    /// Goes back to the default settings, with the shader's toml applied
+    pub fn reset(&mut self, meta: &ShaderMeta) {
+        let shader = std::mem::take(&mut self.data.shader);
+        self.data = ExportData::default();
+        self.set_shader(shader, meta);
+    }
+
+    pub fn set_params(&mut self, params: BTreeMap<String, f32>) {
+        self.data.params = params;
+    }
+
+    pub fn set_adapter(&mut self, adapter: Option<String>) {
+        self.data.adapter = adapter;
+    }
+
+    /// Handles messages from the export thread and starts the next queued job
+    pub fn update(&mut self) {
+        loop {
+            let msg = match self.export_com.try_recv() {
+                Ok(msg) => msg,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    (self.start_export, self.export_com, self._thread) =
+                        spawn_export_thread(&self.gpu, &self.cancel);
+                    Msg::Done(Outcome::Failed("Export thread stopped unexpectedly".into()))
+                }
+            };
+            let Some(job) = self
+                .jobs
+                .iter_mut()
+                .find(|j| matches!(j.status, JobStatus::Running(_)))
+            else {
+                continue;
+            };
+
+            match msg {
+                Msg::Adapter(s) => job.adapter = Some(s),
+                Msg::Progress(p) => job.status = JobStatus::Running(Some(p)),
+                Msg::Done(outcome) => {
+                    job.status = JobStatus::Finished(outcome);
+                    self.state = State::Waiting;
+                }
+            }
+        }
+
+        if self.state == State::Waiting {
+            if let Some(job) = self
+                .jobs
+                .iter_mut()
+                .find(|j| matches!(j.status, JobStatus::Queued))
+            {
+                job.status = JobStatus::Running(None);
+                self.state = State::Generating;
+                self.cancel.store(false, Ordering::Relaxed);
+                let _ = self.start_export.send(job.data.clone());
+            }
+        }
     }
 
     pub fn render_save_ui(&mut self, ui: &mut egui::Ui) {
         containers::CollapsingHeader::new("Export")
             .default_open(true)
-            .show(ui, |ui| match self.state {
-                State::Waiting => self.render_waiting(ui),
-                State::Generating => self.render_generating(ui),
+            .show(ui, |ui| {
+                self.render_settings(ui);
+                ui.add_space(20.0);
+                self.render_queue(ui);
             });
+
+        ui.add_space(20.0);
+        if ui.button("Screenshot").clicked() {
+            self.screenshot_requested = true;
+        }
+        if let Ok(msg) = self.screenshot_rx.try_recv() {
+            self.screenshot_msg = msg;
+        }
+        ui.label(self.screenshot_msg.as_str());
     }
 
-    fn render_generating(&mut self, ui: &mut egui::Ui) {
-        ui.label("Generating...");
-        ui.label(self.thread_msg.as_str());
+    pub fn take_screenshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot_requested)
+    }
 
-        if let Ok(msg) = self.export_com.try_recv() {
-            match msg {
-                Msg::Info(s) => self.thread_msg = s,
-                Msg::Done => self.state = State::Waiting,
-            }
-        }
+    /// Copies the preview texture and saves it as a png on a background thread
+    pub fn screenshot(&mut self, render_state: &egui_wgpu::RenderState, texture: &Texture) {
+        let device = render_state.device.clone();
+        let queue = render_state.queue.clone();
+
+        let readback = texture.create_readback(&device);
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        readback.copy(&mut encoder, texture);
+        let submission = queue.submit(Some(encoder.finish()));
+
+        let tx = self.screenshot_tx.clone();
+        std::thread::spawn(move || {
+            let imgbuf = readback.read(&device, submission).block_on();
+            let file_name = format!(
+                "output/screenshot_{}.png",
+                chrono::Utc::now().format("%Y%m%d_%H%M%S")
+            );
+            let _ = std::fs::create_dir_all("output");
+            let msg = match imgbuf.save(&file_name) {
+                Ok(_) => format!("Saved {}", file_name),
+                Err(e) => format!("Error saving screenshot: {}", e),
+            };
+            let _ = tx.send(msg);
+        });
     }
 
-    fn render_waiting(&mut self, ui: &mut egui::Ui) {
+    fn render_settings(&mut self, ui: &mut egui::Ui) {
         ui.label("Resolution");
         ui.horizontal(|ui| {
             ui.add(widgets::DragValue::new(&mut self.data.width).prefix("width: "));
@@ -96,155 +333,544 @@ impl Export {
         });
         ui.label("Duration");
         ui.add(widgets::DragValue::new(&mut self.data.duration).suffix(" seconds"));
+        ui.label("Samples")
+            .on_hover_text("Per frame, for shaders that accumulate");
+        ui.add(widgets::DragValue::new(&mut self.data.samples).clamp_range(1..=u32::MAX));
+        ui.checkbox(&mut self.data.image_sequence, "Image sequence")
+            .on_hover_text("Keep the png frames instead of encoding a video");
+        ui.label("Device");
+        egui::ComboBox::from_id_source("export_adapter")
+            .selected_text(self.data.adapter.as_deref().unwrap_or("Preview (shared)"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.data.adapter, None, "Preview (shared)");
+                for adapter in self.adapters.iter() {
+                    ui.selectable_value(&mut self.data.adapter, Some(adapter.clone()), adapter);
+                }
+            });
 
         ui.add_space(20.0);
-        if ui.button("Export").clicked() {
-            self.state = State::Generating;
-            let _ = self.start_export.send(self.data.clone());
+        if ui.button("Add to queue").clicked() {
+            self.jobs.push(Job {
+                id: self.next_job_id,
+                data: self.data.clone(),
+                status: JobStatus::Queued,
+                adapter: None,
+            });
+            self.next_job_id += 1;
+        }
+    }
+
+    fn render_queue(&mut self, ui: &mut egui::Ui) {
+        if self.jobs.is_empty() {
+            ui.label("Queue is empty");
+            return;
+        }
+
+        let mut move_up = None;
+        let mut move_down = None;
+        let mut remove = None;
+        let job_count = self.jobs.len();
+        for (idx, job) in self.jobs.iter().enumerate() {
+            ui.separator();
+            ui.label(format!(
+                "#{} {} {}x{} {}s",
+                job.id,
+                shader_name(&job.data.shader),
+                job.data.width,
+                job.data.height,
+                job.data.duration
+            ));
+            if let Some(adapter) = &job.adapter {
+                ui.label(format!("Device: {}", adapter));
+            }
+
+            match &job.status {
+                JobStatus::Queued => {
+                    ui.horizontal(|ui| {
+                        ui.label("Queued");
+                        if ui.add_enabled(idx > 0, egui::Button::new("⬆")).clicked() {
+                            move_up = Some(idx);
+                        }
+                        if ui
+                            .add_enabled(idx + 1 < job_count, egui::Button::new("⬇"))
+                            .clicked()
+                        {
+                            move_down = Some(idx);
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+                }
+                JobStatus::Running(progress) => {
+                    if let Some(p) = progress {
+                        ui.add(widgets::ProgressBar::new(p.fraction()).text(format!(
+                            "{} {}/{}",
+                            p.stage_name(),
+                            p.frames_done,
+                            p.frames_total
+                        )));
+                        ui.label(format!("Elapsed: {:.1}s", p.elapsed.as_secs_f32()));
+                        if let Some(eta) = p.eta() {
+                            ui.label(format!("ETA: {:.1}s", eta.as_secs_f32()));
+                        }
+                    }
+
+                    if self.cancel.load(Ordering::Relaxed) {
+                        ui.label("Cancelling...");
+                    } else if ui.button("Cancel").clicked() {
+                        self.cancel.store(true, Ordering::Relaxed);
+                    }
+                }
+                JobStatus::Finished(outcome) => {
+                    ui.label(match outcome {
+                        Outcome::Saved(path) => format!("Saved {}", path),
+                        Outcome::Failed(e) => format!("Failed: {}", e),
+                        Outcome::Cancelled => "Cancelled".into(),
+                    });
+                }
+            }
+        }
+
+        if let Some(idx) = move_up {
+            self.jobs.swap(idx, idx - 1);
+        }
+        if let Some(idx) = move_down {
+            self.jobs.swap(idx, idx + 1);
+        }
+        if let Some(idx) = remove {
+            self.jobs.remove(idx);
+        }
+
+        ui.separator();
+        if ui.button("Clear finished").clicked() {
+            self.jobs
+                .retain(|j| !matches!(j.status, JobStatus::Finished(_)));
         }
     }
 }
 
-fn export_thread(start: mpsc::Receiver<ExportData>, com: mpsc::Sender<Msg>) {
-    loop {
-        let data = start.recv().unwrap();
-        export_thread_internal(data, com.clone()).block_on();
-
-        com.send(Msg::Done).unwrap();
-    }
-}
-
-async fn export_thread_internal(data: ExportData, com: mpsc::Sender<Msg>) {
-    com.send(Msg::Info("Initlializing".into())).unwrap();
-    let instance = wgpu::Instance::default();
-
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptionsBase::default())
-        .await
-        .unwrap();
-
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::downlevel_defaults(),
-            },
-            None,
-        )
-        .await
-        .unwrap();
-
-    let align_width = data.width + 64 - data.width % 64;
-    let size = (align_width * data.height * 4) as u64;
-    let copy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+fn shader_name(shader: &str) -> &str {
+    let name = shader.rsplit_once('/').map_or(shader, |s| s.1);
+    name.split_once('.').map_or(name, |s| s.0)
+}
 
-    let texture = Texture::new(
-        align_width,
+/// Renders every job in a batch file without opening a window
+pub fn run_batch(path: &str, adapter: Option<&str>) -> Result<(), String> {
+    let batch = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut batch: BatchFile = toml::from_str(&batch).map_err(|e| format!("{}: {}", path, e))?;
+    for job in batch.job.iter_mut() {
+        if job.adapter.is_none() {
+            job.adapter = adapter.map(String::from);
+        }
+    }
+    if let Some(job) = batch.job.iter().find(|j| j.shader.is_empty()) {
+        return Err(format!("{}: job is missing a shader: {:?}", path, job));
+    }
+
+    let job_count = batch.job.len();
+    let mut failed = 0;
+    for (idx, data) in batch.job.into_iter().enumerate() {
+        let prefix = format!("[{}/{} {}]", idx + 1, job_count, shader_name(&data.shader));
+        // One thread per job, a job that panics doesn't take the rest of the batch with it
+        let (start_tx, start_rx) = mpsc::channel();
+        let (com_tx, com_rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        std::thread::spawn(move || export_thread(start_rx, com_tx, cancel, None));
+        let _ = start_tx.send(data);
+
+        let outcome = loop {
+            match com_rx.recv() {
+                Ok(Msg::Adapter(s)) => println!("{} Using {}", prefix, s),
+                Ok(Msg::Progress(p)) => match p.eta() {
+                    Some(eta) => println!(
+                        "{} {} {}/{} (ETA {:.1}s)",
+                        prefix,
+                        p.stage_name(),
+                        p.frames_done,
+                        p.frames_total,
+                        eta.as_secs_f32()
+                    ),
+                    None => println!("{} {}", prefix, p.stage_name()),
+                },
+                Ok(Msg::Done(outcome)) => break outcome,
+                Err(_) => break Outcome::Failed("Export thread stopped unexpectedly".into()),
+            }
+        };
+        match outcome {
+            Outcome::Saved(file) => println!("{} Saved {}", prefix, file),
+            Outcome::Failed(e) => {
+                println!("{} Failed: {}", prefix, e);
+                failed += 1;
+            }
+            Outcome::Cancelled => println!("{} Cancelled", prefix),
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} jobs failed", failed, job_count));
+    }
+    Ok(())
+}
+
+fn spawn_export_thread(
+    gpu: &Gpu,
+    cancel: &Arc<AtomicBool>,
+) -> (
+    mpsc::Sender<ExportData>,
+    mpsc::Receiver<Msg>,
+    std::thread::JoinHandle<()>,
+) {
+    let (start_tx, start_rx) = mpsc::channel();
+    let (com_tx, com_rx) = mpsc::channel();
+    let cancel = cancel.clone();
+    let gpu = gpu.clone();
+    let thread = std::thread::spawn(move || export_thread(start_rx, com_tx, cancel, Some(gpu)));
+    (start_tx, com_rx, thread)
+}
+
+fn export_thread(
+    start: mpsc::Receiver<ExportData>,
+    com: mpsc::Sender<Msg>,
+    cancel: Arc<AtomicBool>,
+    shared: Option<Gpu>,
+) {
+    while let Ok(data) = start.recv() {
+        let outcome =
+            export_thread_internal(data, com.clone(), &cancel, shared.as_ref()).block_on();
+
+        if com.send(Msg::Done(outcome)).is_err() {
+            break;
+        }
+    }
+}
+
+async fn export_thread_internal(
+    data: ExportData,
+    com: mpsc::Sender<Msg>,
+    cancel: &AtomicBool,
+    shared: Option<&Gpu>,
+) -> Outcome {
+    let start = Instant::now();
+    let progress = |stage, frames_done, frames_total| {
+        Msg::Progress(Progress {
+            stage,
+            frames_done,
+            frames_total,
+            elapsed: start.elapsed(),
+        })
+    };
+
+    let _ = com.send(progress(Stage::Initializing, 0, 0));
+    let gpu = match (&data.adapter, shared) {
+        (None, Some(gpu)) => gpu.clone(),
+        (adapter, _) => match Gpu::request(adapter.as_deref()).await {
+            Ok(gpu) => gpu,
+            Err(e) => return Outcome::Failed(e),
+        },
+    };
+    let _ = com.send(Msg::Adapter(gpu.describe()));
+
+    let max_size = gpu.limits.max_texture_dimension_2d;
+    if data.width == 0 || data.height == 0 || data.width > max_size || data.height > max_size {
+        return Outcome::Failed(format!(
+            "{}x{} is outside the supported size of {}x{}",
+            data.width, data.height, max_size, max_size
+        ));
+    }
+    let (device, queue) = (&gpu.device, &gpu.queue);
+
+    // Errors on the shared device would panic the thread instead of failing the job
+    let shader = match prelude::load_shader(&data.shader) {
+        Ok(shader) => shader,
+        Err(e) => return Outcome::Failed(format!("{}: {}", data.shader, e)),
+    };
+    let format = match gpu.output_format(shader.meta.format()) {
+        Ok(format) => format,
+        Err(e) => return Outcome::Failed(format!("{}: {}", data.shader, e)),
+    };
+    if let Err(e) = reflect::check_pipeline(&shader) {
+        return Outcome::Failed(format!("{}: {}", data.shader, e));
+    }
+    let issues = compute::dispatch_issues(
+        shader.kind,
+        &shader.meta,
+        &gpu.limits,
+        data.width,
         data.height,
-        wgpu::TextureFormat::Rgba8Unorm,
-        &device,
-        wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
     );
-    let texture_copy = texture.inner.as_image_copy();
-    let mut compute = Compute::new(&device, &texture, &data.shader);
+    if let Some(issue) = issues.first() {
+        return Outcome::Failed(format!("{}: {}", data.shader, issue));
+    }
+    if compute::accumulates(shader.kind, &shader.meta) {
+        if let Some(issue) = compute::accumulation_issue(&gpu.limits, data.width, data.height) {
+            return Outcome::Failed(format!("{}: {}", data.shader, issue));
+        }
+    }
+    let texture = Texture::new(
+        data.width,
+        data.height,
+        format,
+        device,
+        wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    )
+    .with_read_write(gpu.read_write);
+    let readback = texture.create_readback(device);
+    let mut compute = Compute::from_shader(device, queue, &texture, &data.shader, shader);
+    let params = compute.meta().param_values(&data.params);
+    compute.set_params(queue, &params);
+    // Extra steps would move simulations ahead, only shaders that accumulate get them
+    let samples = match compute.samples() {
+        Some(_) => data.samples.max(1),
+        None => 1,
+    };
+
+    let file_name = data.output.clone().unwrap_or_else(|| {
+        let name = format!(
+            "output/{}_{}",
+            shader_name(&data.shader),
+            chrono::Utc::now().format("%Y%m%d_%H%M%S")
+        );
+        match data.image_sequence {
+            true => name,
+            false => format!("{}.mp4", name),
+        }
+    });
+    let mut frames = Frames {
+        dir: match data.image_sequence {
+            true => PathBuf::from(&file_name),
+            false => PathBuf::from("output/tmp"),
+        },
+        count: 0,
+        keep: false,
+    };
+    if let Err(e) = std::fs::create_dir_all(&frames.dir) {
+        return Outcome::Failed(format!("Error creating output directory: {}", e));
+    }
 
     let fps = 60.;
     let time_per_frame = 1. / fps;
     let frame_count = (data.duration * fps) as usize;
-    com.send(Msg::Info(format!(
-        "Starting to render {} frames",
-        frame_count
-    )))
-    .unwrap();
+    let _ = com.send(progress(Stage::Rendering, 0, frame_count));
 
     for frame in 0..frame_count {
+        if cancel.load(Ordering::Relaxed) {
+            return Outcome::Cancelled;
+        }
+
         let t = time_per_frame * frame as f32;
-        compute.update_time(&queue, t);
-        compute.step(
-            &device,
-            &queue,
-            Some((
-                texture_copy,
-                wgpu::ImageCopyBuffer {
-                    buffer: &copy_buffer,
-                    layout: wgpu::ImageDataLayout {
-                        offset: 0,
-                        bytes_per_row: Some(align_width * 4),
-                        rows_per_image: Some(data.height),
-                    },
-                },
-                wgpu::Extent3d {
-                    width: align_width,
-                    height: data.height,
-                    depth_or_array_layers: 1,
-                },
-            )),
-        );
+        compute.update_time(queue, t);
+        for _ in 1..samples {
+            compute.step(device, queue, None);
+        }
+        let submission = compute.step(device, queue, Some(readback.copy_args(&texture)));
+
+        let saved = readback
+            .read(device, submission)
+            .await
+            .save(frames.path(frame + 1));
+        frames.count = frame + 1;
+        if let Err(e) = saved {
+            return Outcome::Failed(format!("Error saving frame: {}", e));
+        }
 
-        let mut texture_data = Vec::<u8>::with_capacity(size as usize);
+        let _ = com.send(progress(Stage::Rendering, frame + 1, frame_count));
+    }
 
-        let buffer_slice = copy_buffer.slice(..);
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
-        device.poll(wgpu::Maintain::wait()).panic_on_timeout();
-        receiver.recv_async().await.unwrap().unwrap();
-        {
-            let view = buffer_slice.get_mapped_range();
-            texture_data.extend_from_slice(&view[..]);
+    let outcome = match data.image_sequence {
+        true => {
+            frames.keep = true;
+            Outcome::Saved(file_name)
         }
-        copy_buffer.unmap();
-
-        let mut imgbuf = image::ImageBuffer::new(data.width, data.height);
-        for (x, mut y, pixel) in imgbuf.enumerate_pixels_mut() {
-            y = data.height - y - 1;
-            let idx = ((x + y * align_width) * 4) as usize;
-            *pixel = image::Rgba([
-                texture_data[idx],
-                texture_data[idx + 1],
-                texture_data[idx + 2],
-                texture_data[idx + 3],
-            ]);
+        false => {
+            let _ = com.send(progress(Stage::Encoding, frame_count, frame_count));
+            make_video(&file_name, &frames, cancel)
+        }
+    };
+    if let Outcome::Saved(file_name) = &outcome {
+        if let Err(e) = write_metadata(file_name, &data, &gpu) {
+            return Outcome::Failed(format!("Error saving metadata: {}", e));
         }
-        imgbuf
-            .save(format!("output/tmp/image_{}.png", frame + 1))
-            .unwrap();
+    }
+    outcome
+}
+
+/// Settings and device an export was rendered with, saved next to the video
+#[derive(Serialize)]
+struct Metadata<'a> {
+    shader: &'a str,
+    width: u32,
+    height: u32,
+    duration: f32,
+    fps: u32,
+    /// Only used by shaders that accumulate
+    samples: u32,
+
+    adapter: &'a str,
+    backend: String,
+    device_type: String,
+    driver: &'a str,
+    driver_info: &'a str,
+    params: &'a BTreeMap<String, f32>,
+    limits: MetadataLimits,
+}
 
-        com.send(Msg::Info(format!(
-            "Rendered frame {}/{}",
-            frame, frame_count
-        )))
-        .unwrap();
+#[derive(Serialize)]
+struct MetadataLimits {
+    max_texture_dimension_2d: u32,
+    max_storage_textures_per_shader_stage: u32,
+    max_storage_buffer_binding_size: u32,
+    max_compute_workgroup_size_x: u32,
+    max_compute_workgroup_size_y: u32,
+    max_compute_workgroups_per_dimension: u32,
+}
+
+fn write_metadata(file_name: &str, data: &ExportData, gpu: &Gpu) -> Result<(), String> {
+    let metadata = Metadata {
+        shader: &data.shader,
+        width: data.width,
+        height: data.height,
+        duration: data.duration,
+        fps: 60,
+        samples: data.samples,
+
+        adapter: &gpu.info.name,
+        backend: format!("{:?}", gpu.info.backend),
+        device_type: format!("{:?}", gpu.info.device_type),
+        driver: &gpu.info.driver,
+        driver_info: &gpu.info.driver_info,
+        params: &data.params,
+        limits: MetadataLimits {
+            max_texture_dimension_2d: gpu.limits.max_texture_dimension_2d,
+            max_storage_textures_per_shader_stage: gpu.limits.max_storage_textures_per_shader_stage,
+            max_storage_buffer_binding_size: gpu.limits.max_storage_buffer_binding_size,
+            max_compute_workgroup_size_x: gpu.limits.max_compute_workgroup_size_x,
+            max_compute_workgroup_size_y: gpu.limits.max_compute_workgroup_size_y,
+            max_compute_workgroups_per_dimension: gpu.limits.max_compute_workgroups_per_dimension,
+        },
+    };
+
+    let path = std::path::Path::new(file_name).with_extension("toml");
+    let metadata = toml::to_string(&metadata).map_err(|e| e.to_string())?;
+    std::fs::write(path, metadata).map_err(|e| e.to_string())
+}
+
+/// Png frames of an export. They are deleted when it ends, unless they are its result
+struct Frames {
+    dir: PathBuf,
+    /// Frames written so far, numbered from 1
+    count: usize,
+    keep: bool,
+}
+
+impl Frames {
+    fn path(&self, frame: usize) -> PathBuf {
+        self.dir.join(format!("image_{}.png", frame))
     }
-    make_video(com.clone(), frame_count);
 }
 
-fn make_video(com: mpsc::Sender<Msg>, frames: usize) {
-    com.send(Msg::Info("Converting to video".into())).unwrap();
-    let file_name = format!("output/{}.mp4", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+impl Drop for Frames {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        for frame in 1..=self.count {
+            let _ = std::fs::remove_file(self.path(frame));
+        }
+    }
+}
 
-    match std::process::Command::new("ffmpeg")
+fn make_video(file_name: &str, frames: &Frames, cancel: &AtomicBool) -> Outcome {
+    let child = std::process::Command::new("ffmpeg")
+        .arg("-y")
         .arg("-framerate")
         .arg("60")
         .arg("-i")
-        .arg("output/tmp/image_%d.png")
+        .arg(frames.dir.join("image_%d.png"))
         .arg("-c:v")
         .arg("libx264")
         .arg("-r")
         .arg("60")
         .arg("-frames:v")
-        .arg(frames.to_string())
-        .arg(&file_name)
-        .output()
-    {
-        Ok(_) => com.send(Msg::Info(format!("Saved {}", file_name))).unwrap(),
-        Err(e) => com
-            .send(Msg::Info(format!("Error saving file: {}", e)))
-            .unwrap(),
+        .arg(frames.count.to_string())
+        .arg(file_name)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => return Outcome::Failed(format!("Error saving file: {}", e)),
+    };
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = std::fs::remove_file(file_name);
+            return Outcome::Cancelled;
+        }
+
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(Some(status)) => {
+                return Outcome::Failed(format!("Error saving file: ffmpeg {}", status))
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Outcome::Failed(format!("Error saving file: {}", e)),
+        }
+    }
+    Outcome::Saved(file_name.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_shaders_fail_the_job() {
+        let gpu = crate::golden_tests::gpu();
+        let cancel = AtomicBool::new(false);
+        for shader in ["shaders/missing.wgsl", "src/prelude.wgsl"] {
+            let data = ExportData {
+                shader: shader.into(),
+                width: 8,
+                height: 8,
+                ..ExportData::default()
+            };
+            let (com_tx, _com_rx) = mpsc::channel();
+            let outcome = export_thread_internal(data, com_tx, &cancel, Some(gpu)).block_on();
+            assert!(matches!(outcome, Outcome::Failed(_)), "{}", shader);
+        }
+    }
+
+    #[test]
+    fn frames_are_only_kept_as_image_sequences() {
+        let gpu = crate::golden_tests::gpu();
+        let cancel = AtomicBool::new(false);
+        let dir = "target/export-test/sequence";
+        let _ = std::fs::remove_dir_all(dir);
+        let data = ExportData {
+            shader: "shaders/circles.wgsl".into(),
+            width: 8,
+            height: 8,
+            duration: 0.05,
+            output: Some(dir.into()),
+            image_sequence: true,
+            ..ExportData::default()
+        };
+        let (com_tx, _com_rx) = mpsc::channel();
+        let outcome = export_thread_internal(data, com_tx, &cancel, Some(gpu)).block_on();
+        assert!(matches!(outcome, Outcome::Saved(_)));
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 3);
+
+        let frames = Frames {
+            dir: dir.into(),
+            count: 3,
+            keep: false,
+        };
+        drop(frames);
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
     }
 }
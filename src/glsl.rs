@@ -0,0 +1,191 @@
+//! Translates Shadertoy style GLSL into a WGSL fragment shader, so it can go through the
+//! same pipeline as shaders written for Plix.
+
+use naga::{back::wgsl, front::glsl, valid};
+
+/// Declarations the Shadertoy uniforms map onto. Kept on a single line in front of the
+/// user code, so diagnostics only have to skip one line
+const HEADER: &str = "layout(set = 0, binding = 0) uniform Data { uint width; uint height; float t; uint frame; vec4 mouse; } plix_data; \
+    layout(set = 2, binding = 0) uniform texture2D plix_channel0; \
+    layout(set = 2, binding = 1) uniform texture2D plix_channel1; \
+    layout(set = 2, binding = 2) uniform texture2D plix_channel2; \
+    layout(set = 2, binding = 3) uniform texture2D plix_channel3; \
+    layout(set = 2, binding = 4) uniform sampler plix_sampler;";
+
+const FOOTER: &str = "
+vec4 plix_image(vec2 fragCoord) {
+    vec4 color = vec4(0.0);
+    plix_main_image(color, fragCoord);
+    return color;
+}
+layout(location = 0) out vec4 plix_color;
+void main() {
+    plix_color = plix_image(gl_FragCoord.xy);
+}
+";
+
+/// Entry point for the fragment prelude, see `prelude_fragment.wgsl`
+const WRAPPER: &str = "
+fn mainImage(fragCoord: vec2<f32>) -> vec4<f32> {
+    return plix_image(fragCoord);
+}
+";
+
+const DEFINES: &[(&str, &str)] = &[
+    ("mainImage", "plix_main_image"),
+    ("iTime", "plix_data.t"),
+    (
+        "iResolution",
+        "vec3(float(plix_data.width), float(plix_data.height), 1.0)",
+    ),
+    ("iFrame", "int(plix_data.frame)"),
+    ("iMouse", "plix_data.mouse"),
+    ("iChannel0", "sampler2D(plix_channel0, plix_sampler)"),
+    ("iChannel1", "sampler2D(plix_channel1, plix_sampler)"),
+    ("iChannel2", "sampler2D(plix_channel2, plix_sampler)"),
+    ("iChannel3", "sampler2D(plix_channel3, plix_sampler)"),
+];
+
+/// Shadertoy inputs without a Plix equivalent
+const UNSUPPORTED: &[&str] = &[
+    "iTimeDelta",
+    "iFrameRate",
+    "iChannelTime",
+    "iChannelResolution",
+    "iDate",
+    "iSampleRate",
+];
+
+pub fn is_glsl(path: &str) -> bool {
+    path.ends_with(".glsl") || path.ends_with(".frag")
+}
+
+/// Translates a shader defining `void mainImage(out vec4 fragColor, in vec2 fragCoord)`
+/// into WGSL. `files` are `(name, source)` pairs compiled as one shader in the given order,
+/// errors are reported as `name:line:column: message` with lines of the file they are in
+pub fn translate(files: &[(&str, &str)]) -> Result<String, Vec<String>> {
+    let source = files
+        .iter()
+        .map(|(_, source)| *source)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut issues: Vec<String> = UNSUPPORTED
+        .iter()
+        .filter(|name| contains_ident(&source, name))
+        .map(|name| format!("`{}` is not supported by Plix", name))
+        .collect();
+    if !contains_ident(&source, "mainImage") {
+        issues.push("Missing `void mainImage(out vec4 fragColor, in vec2 fragCoord)`".into());
+    }
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    let full = format!("{}\n{}\n{}", HEADER, source, FOOTER);
+    let mut options = glsl::Options::from(naga::ShaderStage::Fragment);
+    for (name, value) in DEFINES {
+        options.defines.insert(name.to_string(), value.to_string());
+    }
+
+    let module = glsl::Frontend::default()
+        .parse(&options, &full)
+        .map_err(|errors| {
+            errors
+                .iter()
+                .map(|e| diagnostic(files, &full, e.meta, &e.kind.to_string()))
+                .collect::<Vec<_>>()
+        })?;
+    let info = valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| {
+            let message = e.as_inner().to_string();
+            match e.spans().next() {
+                Some((span, _)) => vec![diagnostic(files, &full, *span, &message)],
+                None => vec![message],
+            }
+        })?;
+    let wgsl = wgsl::write_string(&module, &info, wgsl::WriterFlags::empty())
+        .map_err(|e| vec![e.to_string()])?;
+
+    Ok(format!("{}\n{}", wgsl, WRAPPER))
+}
+
+/// Errors in the header or footer are reported without a location
+fn diagnostic(files: &[(&str, &str)], full: &str, span: naga::Span, message: &str) -> String {
+    if !span.is_defined() {
+        return message.into();
+    }
+    let location = span.location(full);
+    // The header is the first line
+    let mut line = location.line_number as usize - 1;
+    for (name, source) in files {
+        let lines = source.lines().count().max(1);
+        if (1..=lines).contains(&line) {
+            return format!("{}:{}:{}: {}", name, line, location.line_position, message);
+        }
+        line = line.saturating_sub(lines);
+    }
+    message.into()
+}
+
+fn contains_ident(source: &str, name: &str) -> bool {
+    source
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|word| word == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADERTOY: &str = "void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    vec3 col = 0.5 + 0.5 * cos(iTime + uv.xyx + vec3(0, 2, 4));
+    col += texture(iChannel0, uv).rgb * float(iFrame % 2);
+    fragColor = vec4(col, 1.0);
+}
+";
+
+    #[test]
+    fn translates_shadertoy_default() {
+        let wgsl = translate(&[("image.glsl", SHADERTOY)]).unwrap();
+        naga::front::wgsl::parse_str(&wgsl).unwrap();
+        assert!(wgsl.contains("fn mainImage(fragCoord: vec2<f32>) -> vec4<f32>"));
+        assert!(crate::reflect::check_shader(&wgsl).is_empty());
+    }
+
+    #[test]
+    fn errors_point_to_user_lines() {
+        let source = SHADERTOY.replace("vec3 col", "vec3 col = foo;\n    vec3 col2");
+        let issues = translate(&[("image.glsl", &source)]).unwrap_err();
+        assert!(issues[0].starts_with("image.glsl:3:"), "{:?}", issues);
+
+        let common = "float f() {\n    return 1.0;\n}";
+        let issues = translate(&[("common.glsl", common), ("image.glsl", &source)]).unwrap_err();
+        assert!(issues[0].starts_with("image.glsl:3:"), "{:?}", issues);
+    }
+
+    #[test]
+    fn unsupported_inputs_are_reported() {
+        let source = SHADERTOY.replace("iTime", "iDate.w");
+        assert_eq!(
+            translate(&[("image.glsl", &source)]).unwrap_err(),
+            vec!["`iDate` is not supported by Plix"]
+        );
+    }
+
+    #[test]
+    fn mouse_reads_the_data_uniform() {
+        let source = SHADERTOY.replace("iTime", "iMouse.x / iResolution.x");
+        let wgsl = translate(&[("image.glsl", &source)]).unwrap();
+        assert!(wgsl.contains("mouse: vec4<f32>"), "{}", wgsl);
+        assert!(crate::reflect::check_shader(&wgsl).is_empty());
+    }
+
+    #[test]
+    fn missing_main_image() {
+        let issues = translate(&[("image.glsl", "void main() {}")]).unwrap_err();
+        assert!(issues[0].contains("mainImage"));
+    }
+}
@@ -0,0 +1,390 @@
+//! Renders every shader in `shaders/` offscreen and compares the result against the
+//! reference images in `tests/golden/`. Run with `PLIX_UPDATE_GOLDEN=1` to rewrite
+//! the references after an intended change.
+
+use eframe::wgpu;
+use pollster::FutureExt;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    compute::Compute,
+    gpu::{self, Gpu},
+    prelude,
+    shader_meta::{OutputFormat, ShaderMeta},
+    texture::Texture,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+const TIMES: [f32; 3] = [0., 0.3, 1.7];
+/// Steps per second of simulated time for shaders that keep state between steps
+const STEPS_PER_SECOND: f32 = 60.;
+/// Largest allowed difference of a single channel
+const TOLERANCE: u8 = 3;
+
+const GOLDEN_DIR: &str = "tests/golden";
+const DIFF_DIR: &str = "target/golden-diff";
+
+/// The software adapter can only be set up once per process, so tests share it
+pub(crate) fn gpu() -> &'static Gpu {
+    static GPU: OnceLock<Gpu> = OnceLock::new();
+    GPU.get_or_init(|| Gpu::request_software().block_on().unwrap())
+}
+
+fn target(gpu: &Gpu) -> Texture {
+    Texture::new(
+        WIDTH,
+        HEIGHT,
+        wgpu::TextureFormat::Rgba8Unorm,
+        &gpu.device,
+        wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    )
+}
+
+/// Simulations, which keep buffers or read the output, are stepped from 0 up to `t`
+fn render(gpu: &Gpu, shader: &str, t: f32) -> image::RgbaImage {
+    let texture = target(gpu);
+    let mut compute = Compute::new(&gpu.device, &gpu.queue, &texture, shader);
+    let meta = compute.meta();
+    if !meta.buffers.is_empty() || meta.output.read {
+        let steps = (t * STEPS_PER_SECOND).round() as u32;
+        for i in 0..steps {
+            compute.update_time(&gpu.queue, i as f32 / STEPS_PER_SECOND);
+            compute.step(&gpu.device, &gpu.queue, None);
+        }
+    }
+    step(gpu, &mut compute, &texture, t)
+}
+
+fn step(gpu: &Gpu, compute: &mut Compute, texture: &Texture, t: f32) -> image::RgbaImage {
+    let readback = texture.create_readback(&gpu.device);
+    compute.update_time(&gpu.queue, t);
+    let submission = compute.step(&gpu.device, &gpu.queue, Some(readback.copy_args(texture)));
+    readback.read(&gpu.device, submission).block_on()
+}
+
+/// Returns the number of pixels outside the tolerance and an image highlighting them
+fn compare(actual: &image::RgbaImage, expected: &image::RgbaImage) -> (usize, image::RgbaImage) {
+    let mut mismatched = 0;
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+    for ((a, e), d) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let delta = [0, 1, 2, 3].map(|c| a[c].abs_diff(e[c]));
+        if delta.iter().any(|&c| c > TOLERANCE) {
+            mismatched += 1;
+            *d = image::Rgba([255, delta[0].max(delta[1]).max(delta[2]), 0, 255]);
+        } else {
+            *d = image::Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255]);
+        }
+    }
+    (mismatched, diff)
+}
+
+fn shaders() -> Vec<PathBuf> {
+    let mut shaders: Vec<_> = std::fs::read_dir("shaders")
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| prelude::EXTENSIONS.iter().any(|ext| e == *ext))
+        })
+        .collect();
+    shaders.sort();
+    shaders
+}
+
+#[test]
+fn shaders_match_golden_images() {
+    let gpu = gpu();
+    let update = std::env::var_os("PLIX_UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for shader in shaders() {
+        let name = shader.file_stem().unwrap().to_str().unwrap().to_owned();
+        for t in TIMES {
+            let stem = format!("{}_t{}", name, t);
+            let golden = Path::new(GOLDEN_DIR).join(format!("{}.png", stem));
+            let actual = render(gpu, shader.to_str().unwrap(), t);
+            // A single colour would match almost any regression
+            if actual.pixels().all(|p| p == actual.get_pixel(0, 0)) {
+                failures.push(format!("{}: renders a single colour", stem));
+                continue;
+            }
+
+            if update {
+                std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+                actual.save(&golden).unwrap();
+                continue;
+            }
+
+            let Ok(expected) = image::open(&golden) else {
+                failures.push(format!("{}: missing reference image", golden.display()));
+                continue;
+            };
+            let expected = expected.to_rgba8();
+            if expected.dimensions() != actual.dimensions() {
+                failures.push(format!(
+                    "{}: reference is {:?}, rendered {:?}",
+                    golden.display(),
+                    expected.dimensions(),
+                    actual.dimensions()
+                ));
+                continue;
+            }
+
+            let (mismatched, diff) = compare(&actual, &expected);
+            if mismatched > 0 {
+                std::fs::create_dir_all(DIFF_DIR).unwrap();
+                let diff_path = Path::new(DIFF_DIR).join(format!("{}_diff.png", stem));
+                diff.save(&diff_path).unwrap();
+                actual
+                    .save(Path::new(DIFF_DIR).join(format!("{}.png", stem)))
+                    .unwrap();
+                failures.push(format!(
+                    "{}: {} pixels differ, see {}",
+                    golden.display(),
+                    mismatched,
+                    diff_path.display()
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn background_compile_swaps_in_when_ready() {
+    let gpu = gpu();
+    let texture = target(gpu);
+    let mut compute = Compute::new(&gpu.device, &gpu.queue, &texture, "shaders/idk.wgsl");
+    compute.enable_background_compile(gpu.device.clone(), gpu.queue.clone());
+
+    let circles = "shaders/circles.wgsl";
+    assert!(compute
+        .reload_shader(&gpu.device, &gpu.queue, circles)
+        .is_none());
+    let start = Instant::now();
+    while compute.poll_compiled(&gpu.device, &gpu.queue).is_none() {
+        assert!(start.elapsed() < Duration::from_secs(30), "never compiled");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let actual = step(gpu, &mut compute, &texture, 0.5);
+    assert_eq!(compare(&actual, &render(gpu, circles, 0.5)).0, 0);
+}
+
+#[test]
+fn storage_buffers_persist_until_reset() {
+    let gpu = gpu();
+    let texture = target(gpu);
+    let physarum = "shaders/physarum.wgsl";
+    let mut compute = Compute::new(&gpu.device, &gpu.queue, &texture, physarum);
+
+    let first = step(gpu, &mut compute, &texture, 0.);
+    let mut later = first.clone();
+    for _ in 0..10 {
+        later = step(gpu, &mut compute, &texture, 0.);
+    }
+    assert_ne!(compare(&later, &first).0, 0);
+
+    compute.reset(&gpu.device, &gpu.queue);
+    let reset = step(gpu, &mut compute, &texture, 0.);
+    assert_eq!(compare(&reset, &first).0, 0);
+}
+
+#[test]
+fn output_accumulates_until_reset() {
+    let gpu = gpu();
+    let source = "@compute @workgroup_size(8, 8)\n\
+                  fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n    \
+                  textureStore(texture, id.xy, previous(id.xy) + vec4<f32>(0.1));\n}";
+    let meta: ShaderMeta = toml::from_str("[output]\nread = true").unwrap();
+    // Ping-pong everywhere, and reading the output directly where the adapter can
+    for read_write in [false, gpu.read_write] {
+        let texture = target(gpu).with_read_write(read_write);
+        let shader = prelude::compose_shader("accumulate.wgsl", source, meta.clone()).unwrap();
+        let mut compute =
+            Compute::from_shader(&gpu.device, &gpu.queue, &texture, "accumulate.wgsl", shader);
+
+        let mut image = step(gpu, &mut compute, &texture, 0.);
+        for _ in 0..2 {
+            image = step(gpu, &mut compute, &texture, 0.);
+        }
+        assert!(
+            image.pixels().all(|p| p[0].abs_diff(77) <= 1),
+            "{:?}",
+            image[(0, 0)]
+        );
+
+        compute.reset(&gpu.device, &gpu.queue);
+        let image = step(gpu, &mut compute, &texture, 0.);
+        assert!(
+            image.pixels().all(|p| p[0].abs_diff(26) <= 1),
+            "{:?}",
+            image[(0, 0)]
+        );
+    }
+}
+
+#[test]
+fn samples_average_until_time_or_params_change() {
+    let gpu = gpu();
+    let texture = target(gpu);
+    let source = "@compute @workgroup_size(8, 8)\n\
+                  fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n    \
+                  accumulate(id.xy, vec4<f32>(f32(sample.index % 2u)));\n}";
+    let meta: ShaderMeta =
+        toml::from_str("workgroup_size = [8, 8]\n[output]\naccumulate = true").unwrap();
+    let shader = prelude::compose_shader("average.wgsl", source, meta).unwrap();
+    let mut compute =
+        Compute::from_shader(&gpu.device, &gpu.queue, &texture, "average.wgsl", shader);
+
+    let mut image = step(gpu, &mut compute, &texture, 0.);
+    for _ in 0..3 {
+        image = step(gpu, &mut compute, &texture, 0.);
+    }
+    assert_eq!(compute.samples(), Some(4));
+    assert!(
+        image.pixels().all(|p| p[0].abs_diff(128) <= 1),
+        "{:?}",
+        image[(0, 0)]
+    );
+
+    let image = step(gpu, &mut compute, &texture, 1.);
+    assert_eq!(compute.samples(), Some(1));
+    assert!(image.pixels().all(|p| p[0] == 0), "{:?}", image[(0, 0)]);
+
+    step(gpu, &mut compute, &texture, 1.);
+    compute.set_params(&gpu.queue, &[0.5]);
+    step(gpu, &mut compute, &texture, 1.);
+    assert_eq!(compute.samples(), Some(1));
+
+    // Time doesn't matter to shaders that say so, params still do
+    let meta: ShaderMeta = toml::from_str(
+        "workgroup_size = [8, 8]\n[output]\naccumulate = true\ntime_independent = true",
+    )
+    .unwrap();
+    let shader = prelude::compose_shader("average.wgsl", source, meta).unwrap();
+    let mut compute =
+        Compute::from_shader(&gpu.device, &gpu.queue, &texture, "average.wgsl", shader);
+    let mut image = step(gpu, &mut compute, &texture, 0.);
+    for t in 1..4 {
+        image = step(gpu, &mut compute, &texture, t as f32);
+    }
+    assert_eq!(compute.samples(), Some(4));
+    assert!(
+        image.pixels().all(|p| p[0].abs_diff(128) <= 1),
+        "{:?}",
+        image[(0, 0)]
+    );
+    compute.set_params(&gpu.queue, &[0.5]);
+    step(gpu, &mut compute, &texture, 4.);
+    assert_eq!(compute.samples(), Some(1));
+}
+
+#[test]
+fn float_output_keeps_small_steps() {
+    let gpu = gpu();
+    let source = "@compute @workgroup_size(8, 8)\n\
+                  fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n    \
+                  textureStore(texture, id.xy, previous(id.xy) + vec4<f32>(0.002));\n}";
+    let meta: ShaderMeta =
+        toml::from_str("workgroup_size = [8, 8]\n[output]\nread = true").unwrap();
+    let texture = target(gpu);
+    let shader = prelude::compose_shader("float.wgsl", source, meta.clone()).unwrap();
+    let mut compute = Compute::from_shader(&gpu.device, &gpu.queue, &texture, "float.wgsl", shader);
+    let mut image = step(gpu, &mut compute, &texture, 0.);
+    for _ in 0..9 {
+        image = step(gpu, &mut compute, &texture, 0.);
+    }
+    // Every step rounds up to the next rgba8 value
+    assert_eq!(image[(0, 0)][0], 10);
+
+    let meta = ShaderMeta {
+        format: Some(OutputFormat::Rgba16float),
+        ..meta
+    };
+    let shader = prelude::compose_shader("float.wgsl", source, meta).unwrap();
+    compute
+        .set_shader(&gpu.device, &gpu.queue, "float.wgsl", shader)
+        .unwrap();
+    assert_eq!(compute.format(), wgpu::TextureFormat::Rgba16Float);
+    let texture = Texture::new(
+        WIDTH,
+        HEIGHT,
+        compute.format(),
+        &gpu.device,
+        wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    );
+    compute.update_texture(&gpu.device, &gpu.queue, &texture);
+    let mut image = step(gpu, &mut compute, &texture, 0.);
+    for _ in 0..9 {
+        image = step(gpu, &mut compute, &texture, 0.);
+    }
+    assert!(image.pixels().all(|p| p[0] == 5), "{:?}", image[(0, 0)]);
+}
+
+#[test]
+fn error_scopes_stay_with_their_thread() {
+    let gpu = gpu();
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let source = match i % 2 {
+                    0 => "@compute @workgroup_size(1) fn main() {}",
+                    _ => "@compute @workgroup_size(1) fn main() { let x: u32 = 1.5; }",
+                };
+                let result = gpu::with_error_scope(&gpu.device, || {
+                    gpu.device
+                        .create_shader_module(wgpu::ShaderModuleDescriptor {
+                            label: None,
+                            source: wgpu::ShaderSource::Wgsl(source.into()),
+                        })
+                });
+                result.is_ok() == (i % 2 == 0)
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert!(thread.join().unwrap());
+    }
+}
+
+#[test]
+fn device_errors_keep_the_current_shader() {
+    let gpu = gpu();
+    let texture = target(gpu);
+    let mut compute = Compute::new(&gpu.device, &gpu.queue, &texture, "shaders/circles.wgsl");
+    let before = step(gpu, &mut compute, &texture, 0.5);
+
+    // Naga accepts it, the device's workgroup limit doesn't
+    let source = "@compute @workgroup_size(4096) fn main() {}";
+    let shader = prelude::compose_shader("large.wgsl", source, ShaderMeta::default()).unwrap();
+    assert!(compute
+        .set_shader(&gpu.device, &gpu.queue, "large.wgsl", shader)
+        .is_none());
+    assert_eq!(step(gpu, &mut compute, &texture, 0.5), before);
+}
+
+#[test]
+fn compare_respects_tolerance() {
+    let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, image::Rgba([100 + TOLERANCE, 100, 100, 255]));
+    assert_eq!(compare(&actual, &expected).0, 0);
+
+    actual.put_pixel(1, 1, image::Rgba([100, 100 - TOLERANCE - 1, 100, 255]));
+    assert_eq!(compare(&actual, &expected).0, 1);
+}
@@ -0,0 +1,322 @@
+use eframe::{
+    egui::{self, containers},
+    egui_wgpu, wgpu,
+};
+use pollster::FutureExt;
+use std::sync::{Arc, Mutex};
+
+use crate::shader_meta::OutputFormat;
+
+/// Device and queue used for rendering, along with a description of the adapter
+/// they were created from
+#[derive(Clone)]
+pub struct Gpu {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub info: wgpu::AdapterInfo,
+    pub limits: wgpu::Limits,
+    /// Rgba8 output textures can be read-write storage textures
+    pub read_write: bool,
+    /// Formats the output texture can have on this adapter
+    pub output_formats: Vec<OutputFormat>,
+}
+
+impl Gpu {
+    pub fn from_render_state(render_state: &egui_wgpu::RenderState) -> Self {
+        Self {
+            device: render_state.device.clone(),
+            queue: render_state.queue.clone(),
+            info: render_state.adapter.get_info(),
+            limits: render_state.device.limits(),
+            read_write: supports_read_write(&render_state.adapter, &render_state.device),
+            output_formats: output_formats(&render_state.adapter),
+        }
+    }
+
+    /// Creates a new device on the first adapter whose name contains `adapter`,
+    /// or on the default adapter if no name is given
+    pub async fn request(adapter: Option<&str>) -> Result<Self, String> {
+        let instance = wgpu::Instance::default();
+
+        let adapter = match adapter {
+            Some(name) => find_adapter(instance.enumerate_adapters(wgpu::Backends::all()), name)
+                .ok_or_else(|| format!("No adapter matching \"{}\"", name))?,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptionsBase::default())
+                .await
+                .ok_or("No adapter found")?,
+        };
+        Self::create(adapter).await
+    }
+
+    /// Creates a device on the software fallback adapter, or on the default one
+    /// if there is no fallback
+    #[cfg(test)]
+    pub async fn request_software() -> Result<Self, String> {
+        let instance = wgpu::Instance::default();
+
+        let software = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            })
+            .await;
+        let adapter = match software {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptionsBase::default())
+                .await
+                .ok_or("No adapter found")?,
+        };
+        Self::create(adapter).await
+    }
+
+    async fn create(adapter: wgpu::Adapter) -> Result<Self, String> {
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: adapter.features() & OPTIONAL_FEATURES,
+                    required_limits: wgpu::Limits::downlevel_defaults()
+                        .using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            limits: device.limits(),
+            read_write: supports_read_write(&adapter, &device),
+            output_formats: output_formats(&adapter),
+            info: adapter.get_info(),
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} ({:?})", self.info.name, self.info.backend)
+    }
+
+    /// Texture format for `format`, if the adapter can use it for the output
+    pub fn output_format(&self, format: OutputFormat) -> Result<wgpu::TextureFormat, String> {
+        if !self.output_formats.contains(&format) {
+            return Err(format!(
+                "Output format {} is not supported by {}",
+                format.wgsl(),
+                self.info.name
+            ));
+        }
+        Ok(format.texture_format())
+    }
+}
+
+/// Runs `f` in a validation error scope and returns the first error raised in it.
+/// Error scopes are a stack shared by every thread using a device, so nothing may push or
+/// pop one except through here, where a lock keeps threads from popping each other's
+pub fn with_error_scope<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> Result<T, String> {
+    static SCOPE_LOCK: Mutex<()> = Mutex::new(());
+    let _guard = SCOPE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+    match device.pop_error_scope().block_on() {
+        Some(e) => Err(e.to_string()),
+        None => Ok(value),
+    }
+}
+
+pub fn enumerate_adapters() -> Vec<wgpu::Adapter> {
+    wgpu::Instance::default().enumerate_adapters(wgpu::Backends::all())
+}
+
+/// Finds the first adapter whose name contains `name`, ignoring case
+pub fn find_adapter(adapters: Vec<wgpu::Adapter>, name: &str) -> Option<wgpu::Adapter> {
+    adapters
+        .into_iter()
+        .find(|a| matches_adapter(&a.get_info(), name))
+}
+
+/// Whether the adapter name contains `name`, ignoring case
+pub fn matches_adapter(info: &wgpu::AdapterInfo, name: &str) -> bool {
+    info.name.to_lowercase().contains(&name.to_lowercase())
+}
+
+pub fn describe_adapter(info: &wgpu::AdapterInfo, limits: &wgpu::Limits) -> String {
+    format!(
+        "{}\n  backend: {:?}, type: {:?}\n  driver: {} {}\n  max texture: {}, max workgroups: {}, max storage buffer: {}",
+        info.name,
+        info.backend,
+        info.device_type,
+        info.driver,
+        info.driver_info,
+        limits.max_texture_dimension_2d,
+        limits.max_compute_workgroups_per_dimension,
+        limits.max_storage_buffer_binding_size,
+    )
+}
+
+/// Restricts eframe to the backend of `info` and prefers its device type. Eframe can't
+/// be handed an adapter, call `force_software_driver` first for Cpu adapters. The app
+/// checks which adapter it got with `check_preview_adapter`
+pub fn wgpu_options(info: &wgpu::AdapterInfo) -> egui_wgpu::WgpuConfiguration {
+    let power_preference = match info.device_type {
+        wgpu::DeviceType::DiscreteGpu => wgpu::PowerPreference::HighPerformance,
+        wgpu::DeviceType::IntegratedGpu => wgpu::PowerPreference::LowPower,
+        _ => wgpu::PowerPreference::None,
+    };
+
+    egui_wgpu::WgpuConfiguration {
+        supported_backends: info.backend.into(),
+        power_preference,
+        ..Default::default()
+    }
+}
+
+/// Eframe requests its adapter without `force_fallback_adapter`, so a software adapter
+/// loses to any real GPU on its backend. Mesa can hide the hardware drivers instead,
+/// which has to happen before eframe creates its instance
+pub fn force_software_driver(info: &wgpu::AdapterInfo) {
+    if info.device_type != wgpu::DeviceType::Cpu {
+        return;
+    }
+    match info.backend {
+        // llvmpipe instead of the hardware GL driver
+        wgpu::Backend::Gl => std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1"),
+        // Only loads Vulkan drivers whose manifest matches, lavapipe's is lvp_icd
+        wgpu::Backend::Vulkan if info.name.contains("llvmpipe") => {
+            std::env::set_var("VK_LOADER_DRIVERS_SELECT", "*lvp*")
+        }
+        wgpu::Backend::Vulkan if info.name.contains("SwiftShader") => {
+            std::env::set_var("VK_LOADER_DRIVERS_SELECT", "*swiftshader*")
+        }
+        _ => {}
+    }
+}
+
+/// Fails when eframe created the preview on another adapter than the one requested
+pub fn check_preview_adapter(
+    render_state: &egui_wgpu::RenderState,
+    requested: Option<&str>,
+) -> Result<(), String> {
+    let preview = render_state.adapter.get_info();
+    match requested {
+        Some(name) if !matches_adapter(&preview, name) => Err(format!(
+            "Requested adapter \"{}\" but the preview got {} ({:?})",
+            name, preview.name, preview.backend
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Features used when the adapter has them: timestamp queries for the profiler and
+/// format specific features for read-write output textures
+const OPTIONAL_FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+
+/// Requests `OPTIONAL_FEATURES` on top of the regular device features when the adapter has them
+// egui_wgpu stores the descriptor callback in an `Arc<dyn Fn>` that isn't `Send`
+#[allow(clippy::arc_with_non_send_sync)]
+pub fn with_optional_features(
+    mut config: egui_wgpu::WgpuConfiguration,
+) -> egui_wgpu::WgpuConfiguration {
+    let device_descriptor = config.device_descriptor.clone();
+    config.device_descriptor = Arc::new(move |adapter| {
+        let mut descriptor = device_descriptor(adapter);
+        descriptor.required_features |= adapter.features() & OPTIONAL_FEATURES;
+        descriptor
+    });
+    config
+}
+
+/// Whether the rgba8 output can be bound as a read-write storage texture, otherwise
+/// shaders reading it get a copy of the last step
+pub fn supports_read_write(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+    // GLES only has read-write images in r32 formats, whatever the GL adapter reports
+    adapter.get_info().backend != wgpu::Backend::Gl
+        && device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        && adapter
+            .get_texture_format_features(wgpu::TextureFormat::Rgba8Unorm)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+}
+
+/// Formats that can be written as storage textures, sampled in the preview and copied
+/// back for exports
+pub fn output_formats(adapter: &wgpu::Adapter) -> Vec<OutputFormat> {
+    let usages = wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::RENDER_ATTACHMENT;
+    OutputFormat::ALL
+        .into_iter()
+        .filter(|f| {
+            adapter
+                .get_texture_format_features(f.texture_format())
+                .allowed_usages
+                .contains(usages)
+        })
+        .collect()
+}
+
+pub struct AdapterPanel {
+    adapters: Vec<(wgpu::AdapterInfo, wgpu::Limits)>,
+    preview: wgpu::AdapterInfo,
+    requested: Option<String>,
+    /// Set when the preview runs on another adapter than the requested one
+    mismatch: Option<String>,
+}
+
+impl AdapterPanel {
+    pub fn new(render_state: &egui_wgpu::RenderState, requested: Option<String>) -> Self {
+        let mismatch = check_preview_adapter(render_state, requested.as_deref()).err();
+        // Also for runs without a window to look at, like CI
+        if let Some(mismatch) = &mismatch {
+            eprintln!("{}", mismatch);
+        }
+        let preview = render_state.adapter.get_info();
+
+        Self {
+            adapters: render_state
+                .available_adapters
+                .iter()
+                .map(|a| (a.get_info(), a.limits()))
+                .collect(),
+            preview,
+            requested,
+            mismatch,
+        }
+    }
+
+    /// Returns the adapter picked for exports, if one was clicked
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        let mut export_adapter = None;
+        containers::CollapsingHeader::new("Adapter")
+            .default_open(self.mismatch.is_some())
+            .show(ui, |ui| {
+                ui.label(format!(
+                    "Preview: {} ({:?})",
+                    self.preview.name, self.preview.backend
+                ));
+                if let Some(name) = &self.requested {
+                    ui.label(format!("Requested: {}", name));
+                }
+                if let Some(mismatch) = &self.mismatch {
+                    ui.colored_label(ui.visuals().error_fg_color, mismatch);
+                }
+                ui.label("Restart with --adapter <name> to change the preview adapter");
+
+                for (info, limits) in self.adapters.iter() {
+                    ui.separator();
+                    ui.label(describe_adapter(info, limits));
+                    if ui.button("Use for export").clicked() {
+                        export_adapter = Some(info.name.clone());
+                    }
+                }
+            });
+        export_adapter
+    }
+}
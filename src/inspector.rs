@@ -0,0 +1,161 @@
+use eframe::egui::{self, containers, emath};
+use std::sync::mpsc;
+
+use crate::renderer::ViewUniform;
+
+const MIN_ZOOM: f32 = 1.;
+const MAX_ZOOM: f32 = 256.;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PixelInfo {
+    pub pos: [u32; 2],
+    pub rgba: [u8; 4],
+    /// Channels as stored, above 1 or below 0 for float output formats
+    pub float: [f32; 4],
+}
+
+pub struct Inspector {
+    center: emath::Vec2,
+    zoom: f32,
+
+    enabled: bool,
+    hovered: Option<[u32; 2]>,
+    /// Shadertoy style mouse input for shaders, see `Inspector::mouse`
+    mouse: [f32; 4],
+    pixel: Option<PixelInfo>,
+    pixel_rx: mpsc::Receiver<PixelInfo>,
+}
+
+impl Inspector {
+    pub fn new(pixel_rx: mpsc::Receiver<PixelInfo>) -> Self {
+        Self {
+            center: emath::vec2(0.5, 0.5),
+            zoom: 1.,
+
+            enabled: true,
+            hovered: None,
+            mouse: [0.; 4],
+            pixel: None,
+            pixel_rx,
+        }
+    }
+
+    pub fn view(&self) -> ViewUniform {
+        ViewUniform::new([self.center.x, self.center.y], self.zoom)
+    }
+
+    /// Pixel the renderer should read back this frame
+    pub fn pick(&self) -> Option<[u32; 2]> {
+        if self.enabled {
+            self.hovered
+        } else {
+            None
+        }
+    }
+
+    /// Pixel under the pointer while the primary button is down in xy, pixel where it went
+    /// down in zw, with z negative once it is released
+    pub fn mouse(&self) -> [f32; 4] {
+        self.mouse
+    }
+
+    pub fn reset(&mut self) {
+        self.center = emath::vec2(0.5, 0.5);
+        self.zoom = 1.;
+    }
+
+    pub fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response, texture: [u32; 2]) {
+        while let Ok(p) = self.pixel_rx.try_recv() {
+            self.pixel = Some(p);
+        }
+
+        let rect = response.rect;
+        if response.double_clicked() {
+            self.reset();
+        }
+        if response.dragged() {
+            let delta = response.drag_delta();
+            self.center.x -= delta.x / rect.width() / self.zoom;
+            self.center.y += delta.y / rect.height() / self.zoom;
+        }
+
+        self.hovered = None;
+        if let Some(pointer) = response.hover_pos() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0. {
+                let before = self.screen_to_uv(rect, pointer);
+                self.zoom = (self.zoom * (scroll * 0.005).exp()).clamp(MIN_ZOOM, MAX_ZOOM);
+                let after = self.screen_to_uv(rect, pointer);
+                self.center += before - after;
+            }
+
+            let uv = self.screen_to_uv(rect, pointer);
+            if (0.0..1.0).contains(&uv.x) && (0.0..1.0).contains(&uv.y) {
+                self.hovered = Some([
+                    (uv.x * texture[0] as f32) as u32,
+                    (uv.y * texture[1] as f32) as u32,
+                ]);
+            }
+        }
+        self.clamp_center();
+
+        let (pressed, down) = ui.input(|i| (i.pointer.primary_pressed(), i.pointer.primary_down()));
+        let down = down && response.is_pointer_button_down_on();
+        match self.hovered {
+            Some([x, y]) if down => {
+                let [x, y] = [x as f32, y as f32];
+                self.mouse = match pressed {
+                    true => [x, y, x, y],
+                    false => [x, y, self.mouse[2], self.mouse[3]],
+                };
+            }
+            _ if !down => self.mouse[2] = -self.mouse[2].abs(),
+            _ => {}
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        containers::CollapsingHeader::new("View")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Zoom: {:.2}x", self.zoom));
+                    if ui.button("Reset").clicked() {
+                        self.reset();
+                    }
+                });
+                ui.checkbox(&mut self.enabled, "Pixel inspector");
+
+                if !self.enabled {
+                    return;
+                }
+                match (self.hovered, self.pixel) {
+                    (Some(_), Some(p)) => {
+                        let [r, g, b, a] = p.rgba;
+                        ui.label(format!("Pixel: {}, {}", p.pos[0], p.pos[1]));
+                        ui.label(format!("RGBA: {} {} {} {}", r, g, b, a));
+                        let [r, g, b, a] = p.float;
+                        ui.label(format!("RGBA: {:.3} {:.3} {:.3} {:.3}", r, g, b, a));
+                    }
+                    _ => {
+                        ui.label("Hover the canvas to inspect a pixel");
+                    }
+                }
+            });
+    }
+
+    /// Maps a screen position to texture uv, with v pointing up like the shader y coordinate
+    fn screen_to_uv(&self, rect: emath::Rect, pos: emath::Pos2) -> emath::Vec2 {
+        let base = emath::vec2(
+            (pos.x - rect.left()) / rect.width(),
+            1. - (pos.y - rect.top()) / rect.height(),
+        );
+        self.center + (base - emath::vec2(0.5, 0.5)) / self.zoom
+    }
+
+    fn clamp_center(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center.x = self.center.x.clamp(half, 1. - half);
+        self.center.y = self.center.y.clamp(half, 1. - half);
+    }
+}
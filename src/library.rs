@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use crate::{shader_meta::ShaderMeta, shadertoy};
+
+/// Starter shader for "New from template"
+pub struct Template {
+    pub name: &'static str,
+    pub source: &'static str,
+    /// Sidecar toml written next to the new shader
+    pub meta: &'static str,
+}
+
+pub const TEMPLATES: [Template; 4] = [
+    Template {
+        name: "Blank",
+        source: include_str!("templates/blank.wgsl"),
+        meta: include_str!("templates/blank.toml"),
+    },
+    Template {
+        name: "SDF raymarcher",
+        source: include_str!("templates/sdf_raymarcher.wgsl"),
+        meta: include_str!("templates/sdf_raymarcher.toml"),
+    },
+    Template {
+        name: "Particle field",
+        source: include_str!("templates/particle_field.wgsl"),
+        meta: include_str!("templates/particle_field.toml"),
+    },
+    Template {
+        name: "Feedback",
+        source: include_str!("templates/feedback.wgsl"),
+        meta: include_str!("templates/feedback.toml"),
+    },
+];
+
+/// Writes `template` to `dir` as a WGSL file named after `name`, with a number added
+/// if the name is taken
+pub fn create(dir: &Path, name: &str, template: &Template) -> Result<PathBuf, String> {
+    let path = unique_path(dir, &file_stem(name)?, Some("wgsl"));
+    std::fs::write(&path, template.source).map_err(|e| e.to_string())?;
+    std::fs::write(ShaderMeta::path(path.to_str().unwrap()), template.meta)
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Copies the shader, its toml or the whole Shadertoy project to `<name>_copy`
+pub fn duplicate(path: &Path) -> Result<PathBuf, String> {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let copy = unique_path(
+        path.parent().unwrap_or(Path::new("")),
+        &format!("{}_copy", stem),
+        path.extension().and_then(|e| e.to_str()),
+    );
+    if path.is_dir() {
+        copy_dir(path, &copy).map_err(|e| e.to_string())?;
+    } else {
+        std::fs::copy(path, &copy).map_err(|e| e.to_string())?;
+    }
+    let meta = sidecar(path);
+    if meta.exists() {
+        std::fs::copy(meta, sidecar(&copy)).map_err(|e| e.to_string())?;
+    }
+    Ok(copy)
+}
+
+/// Renames the shader and its toml, keeping the extension. Fails instead of replacing
+/// another shader
+pub fn rename(path: &Path, name: &str) -> Result<PathBuf, String> {
+    let mut renamed = path.with_file_name(file_stem(name)?);
+    if let Some(extension) = path.extension() {
+        renamed.set_extension(extension);
+    }
+    if renamed == path {
+        return Ok(renamed);
+    }
+    if renamed.exists() || sidecar(&renamed).exists() {
+        return Err(format!("{} already exists", renamed.display()));
+    }
+    move_with_sidecar(path, &renamed)?;
+    Ok(renamed)
+}
+
+/// Moves the shader and its toml into `trash`, older copies with the same name are
+/// kept
+pub fn delete(path: &Path, trash: &Path) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(trash).map_err(|e| e.to_string())?;
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let target = unique_path(trash, stem, path.extension().and_then(|e| e.to_str()));
+    move_with_sidecar(path, &target)?;
+    Ok(target)
+}
+
+fn file_stem(name: &str) -> Result<String, String> {
+    if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return Err("The name needs at least one letter or digit".into());
+    }
+    Ok(shadertoy::slug(name))
+}
+
+fn sidecar(path: &Path) -> PathBuf {
+    ShaderMeta::path(path.to_str().unwrap())
+}
+
+/// `parent/name.extension`, or `name_2`, `name_3`... if it or its toml exists
+fn unique_path(parent: &Path, name: &str, extension: Option<&str>) -> PathBuf {
+    let path = |name: &str| {
+        let path = parent.join(name);
+        match extension {
+            Some(extension) => path.with_extension(extension),
+            None => path,
+        }
+    };
+    let mut unique = path(name);
+    let mut i = 2;
+    while unique.exists() || sidecar(&unique).exists() {
+        unique = path(&format!("{}_{}", name, i));
+        i += 1;
+    }
+    unique
+}
+
+fn move_with_sidecar(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::rename(from, to).map_err(|e| e.to_string())?;
+    let meta = sidecar(from);
+    if meta.exists() {
+        std::fs::rename(meta, sidecar(to)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?.path();
+        let target = to.join(entry.file_name().unwrap());
+        if entry.is_dir() {
+            copy_dir(&entry, &target)?;
+        } else {
+            std::fs::copy(&entry, &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude, reflect};
+
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = Path::new("target/library-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn templates_compile() {
+        for template in TEMPLATES.iter() {
+            let meta: ShaderMeta = toml::from_str(template.meta).unwrap();
+            let shader = prelude::compose_shader("new.wgsl", template.source, meta).unwrap();
+            let diagnostics = reflect::diagnostics(&shader.source);
+            assert!(
+                diagnostics.is_empty(),
+                "{}: {:?}",
+                template.name,
+                diagnostics
+            );
+            let mut issues = reflect::check_entry_points(&shader.source, &shader.meta);
+            issues.extend(reflect::check_buffers(&shader.source, &shader.meta.buffers));
+            assert!(issues.is_empty(), "{}: {:?}", template.name, issues);
+        }
+    }
+
+    #[test]
+    fn new_shaders_get_unique_names() {
+        let dir = empty_dir("create");
+        let first = create(&dir, "My Shader", &TEMPLATES[0]).unwrap();
+        assert_eq!(first, dir.join("my_shader.wgsl"));
+        assert!(dir.join("my_shader.toml").exists());
+        let second = create(&dir, "my shader", &TEMPLATES[1]).unwrap();
+        assert_eq!(second, dir.join("my_shader_2.wgsl"));
+        assert!(create(&dir, "!?", &TEMPLATES[0]).is_err());
+
+        let copy = duplicate(&first).unwrap();
+        assert_eq!(copy, dir.join("my_shader_copy.wgsl"));
+        assert!(dir.join("my_shader_copy.toml").exists());
+    }
+
+    #[test]
+    fn rename_moves_the_toml_and_keeps_others() {
+        let dir = empty_dir("rename");
+        let a = create(&dir, "a", &TEMPLATES[0]).unwrap();
+        let b = create(&dir, "b", &TEMPLATES[0]).unwrap();
+        assert!(rename(&a, "b").is_err());
+
+        let c = rename(&a, "C").unwrap();
+        assert_eq!(c, dir.join("c.wgsl"));
+        assert!(!a.exists() && !dir.join("a.toml").exists());
+        assert!(dir.join("c.toml").exists());
+        assert!(b.exists());
+    }
+
+    #[test]
+    fn deleted_shaders_go_to_trash() {
+        let dir = empty_dir("delete");
+        let trash = dir.join("trash");
+        for expected in ["a.wgsl", "a_2.wgsl"] {
+            let a = create(&dir, "a", &TEMPLATES[0]).unwrap();
+            assert_eq!(delete(&a, &trash).unwrap(), trash.join(expected));
+            assert!(!a.exists() && !dir.join("a.toml").exists());
+        }
+        assert!(trash.join("a_2.toml").exists());
+    }
+}
@@ -0,0 +1,217 @@
+use eframe::egui::{self, containers};
+use std::{
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    glsl,
+    prelude::{self, Shader},
+    reflect::{self, Diagnostic},
+    shader_meta::ShaderMeta,
+    shadertoy,
+};
+
+/// How long the source has to stay unchanged before it is validated
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+struct Request {
+    path: String,
+    /// Unsaved source from the editor, `None` reads the file
+    text: Option<String>,
+}
+
+struct Validated {
+    path: String,
+    /// Only set when naga found no errors
+    shader: Option<Shader>,
+    diagnostics: Vec<Diagnostic>,
+    time: Duration,
+}
+
+/// Validates every change on a background thread and only hands over shaders that
+/// compile, so the preview keeps the last working version while typing
+pub struct Live {
+    pub enabled: bool,
+
+    request_tx: mpsc::Sender<Request>,
+    validated_rx: mpsc::Receiver<Validated>,
+    _thread: std::thread::JoinHandle<()>,
+
+    /// Errors of the last validated source and the shader it was for
+    path: String,
+    diagnostics: Vec<Diagnostic>,
+    validation_time: Option<Duration>,
+    compile_time: Option<Duration>,
+}
+
+impl Live {
+    pub fn new(enabled: bool) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (validated_tx, validated_rx) = mpsc::channel();
+        let thread = std::thread::spawn(move || validate_thread(request_rx, validated_tx));
+
+        Self {
+            enabled,
+
+            request_tx,
+            validated_rx,
+            _thread: thread,
+
+            path: String::new(),
+            diagnostics: Vec::new(),
+            validation_time: None,
+            compile_time: None,
+        }
+    }
+
+    pub fn submit(&self, path: &str, text: Option<String>) {
+        let _ = self.request_tx.send(Request {
+            path: path.into(),
+            text,
+        });
+    }
+
+    /// The latest shader that passed validation, ready to be swapped in
+    pub fn update(&mut self) -> Option<(String, Shader)> {
+        let mut ready = None;
+        while let Ok(v) = self.validated_rx.try_recv() {
+            self.path = v.path.clone();
+            self.diagnostics = v.diagnostics;
+            self.validation_time = Some(v.time);
+            // Results that arrive after live mode was switched off are only shown
+            ready = v.shader.filter(|_| self.enabled).map(|s| (v.path, s));
+        }
+        ready
+    }
+
+    /// Time `Compute` took to create the pipeline of the last swapped in shader
+    pub fn set_compile_time(&mut self, time: Duration) {
+        self.compile_time = Some(time);
+    }
+
+    /// Errors of the last validated version of `path`, including unsaved edits
+    pub fn diagnostics(&self, path: &str) -> Option<&[Diagnostic]> {
+        (self.enabled && self.path == path).then_some(self.diagnostics.as_slice())
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        containers::CollapsingHeader::new("Live mode")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, "Apply edits while typing")
+                    .on_hover_text("Edits are only swapped in once they compile");
+                if let Some(time) = self.compile_time {
+                    ui.label(format!("Pipeline: {:.1} ms", time.as_secs_f32() * 1000.));
+                }
+                if !self.enabled {
+                    return;
+                }
+                if let Some(time) = self.validation_time {
+                    ui.label(format!("Validation: {:.1} ms", time.as_secs_f32() * 1000.));
+                }
+                if !self.diagnostics.is_empty() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!(
+                            "{} error(s), showing the last working version",
+                            self.diagnostics.len()
+                        ),
+                    );
+                }
+            });
+    }
+}
+
+fn validate_thread(request_rx: mpsc::Receiver<Request>, validated_tx: mpsc::Sender<Validated>) {
+    while let Ok(mut request) = request_rx.recv() {
+        // Wait until the source stops changing, only the newest version is validated
+        loop {
+            match request_rx.recv_timeout(DEBOUNCE) {
+                Ok(newer) => request = newer,
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let start = Instant::now();
+        let (shader, diagnostics) = validate(&request.path, request.text.as_deref());
+        let validated = Validated {
+            path: request.path,
+            shader,
+            diagnostics,
+            time: start.elapsed(),
+        };
+        if validated_tx.send(validated).is_err() {
+            return;
+        }
+    }
+}
+
+/// Builds the shader like `prelude::load_shader` and checks it with naga
+fn validate(path: &str, text: Option<&str>) -> (Option<Shader>, Vec<Diagnostic>) {
+    let shader = match text {
+        Some(text) => ShaderMeta::load(path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            .and_then(|meta| prelude::compose_shader(path, text, meta)),
+        None => prelude::load_shader(path),
+    };
+    let shader = match shader {
+        Ok(s) => s,
+        Err(e) => {
+            let diagnostic = Diagnostic {
+                line: None,
+                message: e.to_string(),
+            };
+            return (None, vec![diagnostic]);
+        }
+    };
+
+    let mut diagnostics = reflect::diagnostics(&shader.source);
+    // Translated GLSL doesn't have the lines of the file
+    if glsl::is_glsl(path) || shadertoy::is_project(Path::new(path)) {
+        diagnostics.iter_mut().for_each(|d| d.line = None);
+    }
+    (diagnostics.is_empty().then_some(shader), diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "shaders/idk.wgsl";
+
+    #[test]
+    fn broken_source_is_not_swapped_in() {
+        let (shader, diagnostics) = validate(PATH, Some("fn f() {}\nfn main( {"));
+        assert!(shader.is_none());
+        assert_eq!(diagnostics[0].line, Some(2));
+
+        let source =
+            "@compute @workgroup_size(1)\nfn main() { textureStore(texture, vec2(0), vec4(1.)); }";
+        let (shader, diagnostics) = validate(PATH, Some(source));
+        assert!(shader.is_some(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn library_file_is_read_without_text() {
+        let (shader, diagnostics) = validate(PATH, None);
+        assert!(shader.is_some(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn quick_edits_are_validated_once() {
+        let live = Live::new(true);
+        for text in ["fn a(", "fn a() {", "fn a() {}"] {
+            live.submit(PATH, Some(text.into()));
+        }
+        let validated = live.validated_rx.recv_timeout(DEBOUNCE * 20).unwrap();
+        assert!(
+            validated.diagnostics.is_empty(),
+            "{:?}",
+            validated.diagnostics
+        );
+        assert!(live.validated_rx.recv_timeout(DEBOUNCE * 2).is_err());
+    }
+}
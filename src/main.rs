@@ -3,22 +3,106 @@ use eframe::egui;
 
 mod app;
 mod compute;
+mod dispatch;
+mod editor;
 mod export;
+mod glsl;
+#[cfg(test)]
+mod golden_tests;
+mod gpu;
+mod inspector;
+mod library;
+mod live;
+mod playback;
+mod prelude;
+mod profiler;
+mod reflect;
 mod renderer;
+mod settings;
 mod shader_manager;
+mod shader_meta;
+mod shadertoy;
 mod texture;
+mod thumbnails;
+
+const USAGE: &str = "Usage: plix [--adapter <name>] [--batch <file.toml>] [--list-adapters]";
+
+#[derive(Default)]
+struct Args {
+    batch: Option<String>,
+    adapter: Option<String>,
+    list_adapters: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--batch" => args.batch = Some(iter.next().ok_or(USAGE)?),
+                "--adapter" => args.adapter = Some(iter.next().ok_or(USAGE)?),
+                "--list-adapters" => args.list_adapters = true,
+                _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE)),
+            }
+        }
+        Ok(args)
+    }
+}
 
 fn main() -> eframe::Result<()> {
-    let options = eframe::NativeOptions {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if args.list_adapters {
+        for adapter in gpu::enumerate_adapters() {
+            println!(
+                "{}",
+                gpu::describe_adapter(&adapter.get_info(), &adapter.limits())
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(path) = args.batch {
+        if let Err(e) = export::run_batch(&path, args.adapter.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let mut options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder {
             decorations: Some(false),
             ..Default::default()
         },
         ..Default::default()
     };
+    if let Some(name) = &args.adapter {
+        match gpu::find_adapter(gpu::enumerate_adapters(), name) {
+            Some(adapter) => {
+                let info = adapter.get_info();
+                gpu::force_software_driver(&info);
+                options.wgpu_options = gpu::wgpu_options(&info);
+            }
+            None => {
+                eprintln!("No adapter matching \"{}\"", name);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    options.wgpu_options = gpu::with_optional_features(options.wgpu_options);
+
     eframe::run_native(
         "Plix",
         options,
-        Box::new(|cc| Box::new(App::new(cc).unwrap())),
+        Box::new(|cc| Box::new(App::new(cc, args.adapter).unwrap())),
     )
 }
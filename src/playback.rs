@@ -0,0 +1,62 @@
+use eframe::egui::{self, containers, widgets};
+use serde::{Deserialize, Serialize};
+
+/// Time passed to the preview, advanced by the frame time unless paused
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Playback {
+    pub paused: bool,
+    pub time: f64,
+}
+
+impl Playback {
+    pub fn advance(&mut self, dt: f32) {
+        if !self.paused {
+            self.time += dt as f64;
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        containers::CollapsingHeader::new("Playback")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(if self.paused { "Play" } else { "Pause" })
+                        .clicked()
+                    {
+                        self.toggle();
+                    }
+                    if ui.button("Restart").clicked() {
+                        self.time = 0.;
+                    }
+                    ui.add(
+                        widgets::DragValue::new(&mut self.time)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f64::MAX)
+                            .suffix(" s"),
+                    );
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_time_does_not_advance() {
+        let mut playback = Playback::default();
+        playback.advance(0.5);
+        assert_eq!(playback.time, 0.5);
+
+        playback.toggle();
+        playback.advance(0.5);
+        assert_eq!(playback.time, 0.5);
+    }
+}
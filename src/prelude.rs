@@ -0,0 +1,305 @@
+use crate::{
+    glsl,
+    shader_meta::{Output, OutputFormat, ShaderMeta},
+    shadertoy,
+};
+use std::path::Path;
+
+const PRELUDE: &str = include_str!("prelude.wgsl");
+const PRELUDE_COMPUTE: &str = include_str!("prelude_compute.wgsl");
+/// For shaders that read the output, works everywhere by reading a copy of the last step
+const PRELUDE_COMPUTE_PREVIOUS: &str = include_str!("prelude_compute_previous.wgsl");
+/// Replaces `PRELUDE_COMPUTE_PREVIOUS` where the output can be a read-write storage texture
+const PRELUDE_COMPUTE_READ_WRITE: &str = include_str!("prelude_compute_read_write.wgsl");
+const PRELUDE_COMPUTE_ACCUMULATE: &str = include_str!("prelude_compute_accumulate.wgsl");
+const PRELUDE_FRAGMENT: &str = include_str!("prelude_fragment.wgsl");
+const NO_PRELUDE: &str = "// plix: no-prelude";
+/// Start of the output declaration in the compute preludes, up to its format
+const OUTPUT_DECLARATION: &str = "var texture: texture_storage_2d<";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderKind {
+    /// Writes to the output texture from `fn main` with one invocation per pixel
+    Compute,
+    /// Returns the color of a pixel from `fn mainImage(fragCoord: vec2<f32>) -> vec4<f32>`
+    Fragment,
+}
+
+impl ShaderKind {
+    /// Fragment when the shader declares a function named `mainImage`, comments aside
+    pub fn detect(source: &str) -> Self {
+        let code = strip_comments(source);
+        let words = code
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty());
+        if words
+            .clone()
+            .zip(words.skip(1))
+            .any(|pair| pair == ("fn", "mainImage"))
+        {
+            ShaderKind::Fragment
+        } else {
+            ShaderKind::Compute
+        }
+    }
+}
+
+/// Blanks out line and (nested) block comments, WGSL has no strings to skip
+fn strip_comments(source: &str) -> String {
+    let mut code = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('*')) => {
+                chars.next();
+                depth += 1;
+            }
+            ('*', Some('/')) if depth > 0 => {
+                chars.next();
+                depth -= 1;
+            }
+            ('/', Some('/')) if depth == 0 => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        code.push('\n');
+                        break;
+                    }
+                }
+            }
+            _ if depth > 0 => {}
+            _ => code.push(c),
+        }
+        if depth > 0 {
+            code.push(' ');
+        }
+    }
+    code
+}
+
+/// Files `ShaderManager` lists as shaders
+pub const EXTENSIONS: &[&str] = &["wgsl", "glsl", "frag"];
+
+#[derive(Clone)]
+pub struct Shader {
+    /// WGSL with the prelude
+    pub source: String,
+    pub kind: ShaderKind,
+    pub meta: ShaderMeta,
+}
+
+/// Reads a shader and its toml and adds the prelude. GLSL and Shadertoy projects are
+/// translated to WGSL first and bring their own declarations instead of the prelude
+pub fn load_shader(path: &str) -> std::io::Result<Shader> {
+    let meta = ShaderMeta::load(path)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let invalid = |issues: Vec<String>| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, issues.join("\n"))
+    };
+
+    if shadertoy::is_project(Path::new(path)) {
+        let wgsl = shadertoy::load(Path::new(path)).map_err(invalid)?;
+        return Ok(Shader {
+            source: format!("{}\n{}", wgsl, PRELUDE_FRAGMENT),
+            kind: ShaderKind::Fragment,
+            meta,
+        });
+    }
+
+    let source = std::fs::read_to_string(path)?;
+    compose_shader(path, &source, meta)
+}
+
+/// Like `load_shader`, but with `source` in place of the file's contents
+pub fn compose_shader(path: &str, source: &str, meta: ShaderMeta) -> std::io::Result<Shader> {
+    let invalid = |issues: Vec<String>| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, issues.join("\n"))
+    };
+    if glsl::is_glsl(path) {
+        let wgsl = glsl::translate(&[(path, source)]).map_err(invalid)?;
+        return Ok(Shader {
+            source: format!("{}\n{}", wgsl, PRELUDE_FRAGMENT),
+            kind: ShaderKind::Fragment,
+            meta,
+        });
+    }
+
+    let kind = ShaderKind::detect(source);
+    Ok(Shader {
+        source: format!(
+            "{}\n{}{}{}",
+            with_prelude(source, kind, &meta.output),
+            meta.params_wgsl(),
+            meta.constants_wgsl(),
+            meta.buffers_wgsl()
+        ),
+        kind,
+        meta,
+    })
+}
+
+/// The prelude goes after the user code, so line numbers in errors still match the file.
+/// Fragment shaders always get their entry points, even without the prelude. Compute
+/// shaders get `previous()` and `accumulate()` when their `output` asks for them
+pub fn with_prelude(source: &str, kind: ShaderKind, output: &Output) -> String {
+    let prelude = !source.lines().any(|l| l.trim() == NO_PRELUDE);
+    let mut compute = match output.read {
+        true => PRELUDE_COMPUTE_PREVIOUS,
+        false => PRELUDE_COMPUTE,
+    }
+    .to_owned();
+    if output.accumulate {
+        compute = format!("{}\n{}", compute, PRELUDE_COMPUTE_ACCUMULATE);
+    }
+    match (kind, prelude) {
+        (ShaderKind::Compute, true) => format!("{}\n{}\n{}", source, PRELUDE, compute),
+        (ShaderKind::Compute, false) => source.into(),
+        (ShaderKind::Fragment, true) => format!("{}\n{}\n{}", source, PRELUDE, PRELUDE_FRAGMENT),
+        (ShaderKind::Fragment, false) => format!("{}\n{}", source, PRELUDE_FRAGMENT),
+    }
+}
+
+/// Binds the output as a read-write storage texture instead of reading a copy of it.
+/// `None` if the shader doesn't read the output through the prelude
+pub fn with_read_write_output(source: &str) -> Option<String> {
+    let start = source.rfind(PRELUDE_COMPUTE_PREVIOUS)?;
+    let mut source = source.to_owned();
+    source.replace_range(
+        start..start + PRELUDE_COMPUTE_PREVIOUS.len(),
+        PRELUDE_COMPUTE_READ_WRITE,
+    );
+    Some(source)
+}
+
+/// Declares the output with `format` instead of the `rgba8unorm` of the compute preludes
+pub fn with_output_format(source: &str, format: OutputFormat) -> String {
+    let declaration = format!("{}{},", OUTPUT_DECLARATION, OutputFormat::Rgba8unorm.wgsl());
+    let Some(start) = source.rfind(&declaration) else {
+        return source.into();
+    };
+    let mut source = source.to_owned();
+    source.replace_range(
+        start..start + declaration.len(),
+        &format!("{}{},", OUTPUT_DECLARATION, format.wgsl()),
+    );
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::wgpu::naga;
+
+    #[test]
+    fn prelude_is_valid() {
+        naga::front::wgsl::parse_str(&format!("{}\n{}", PRELUDE, PRELUDE_COMPUTE)).unwrap();
+    }
+
+    #[test]
+    fn output_can_be_read() {
+        let source = "@compute @workgroup_size(1) fn main() {\n    \
+                      textureStore(texture, vec2(0u), previous(vec2(0u)));\n}";
+        let output = Output {
+            read: true,
+            ..Output::default()
+        };
+        let copy = with_prelude(source, ShaderKind::Compute, &output);
+        assert!(crate::reflect::diagnostics(&copy).is_empty());
+        let read_write = with_read_write_output(&copy).unwrap();
+        assert!(read_write.contains("read_write"));
+        assert!(crate::reflect::diagnostics(&read_write).is_empty());
+
+        let write_only = with_prelude(source, ShaderKind::Compute, &Output::default());
+        assert!(with_read_write_output(&write_only).is_none());
+    }
+
+    #[test]
+    fn output_format_is_replaced() {
+        let source = "@compute @workgroup_size(1) fn main() {\n    \
+                      textureStore(texture, vec2(0u), vec4(2.));\n}";
+        let full = with_prelude(source, ShaderKind::Compute, &Output::default());
+        let float = with_output_format(&full, OutputFormat::Rgba16float);
+        assert!(float.contains("texture_storage_2d<rgba16float, write>"));
+        assert!(crate::reflect::diagnostics(&float).is_empty());
+        assert_eq!(with_output_format(&full, OutputFormat::Rgba8unorm), full);
+    }
+
+    #[test]
+    fn samples_can_be_accumulated() {
+        let source = "@compute @workgroup_size(1) fn main() {\n    \
+                      accumulate(vec2(0u), vec4(f32(sample.seed & 1u)));\n}";
+        let output = Output {
+            accumulate: true,
+            ..Output::default()
+        };
+        let full = with_prelude(source, ShaderKind::Compute, &output);
+        assert!(crate::reflect::diagnostics(&full).is_empty());
+        let plain = with_prelude(source, ShaderKind::Compute, &Output::default());
+        assert!(!crate::reflect::diagnostics(&plain).is_empty());
+    }
+
+    #[test]
+    fn user_helpers_keep_their_names() {
+        let source = "struct Data { t: f32 };\n\
+                      fn noise(p: vec2<f32>) -> f32 { return fract(sin(dot(p, vec2(12.9, 78.2)))); }\n\
+                      fn palette(t: f32) -> vec3<f32> { return vec3(t); }\n\
+                      fn smin(a: f32, b: f32) -> f32 { return min(a, b); }\n\
+                      @compute @workgroup_size(1) fn main() {\n    \
+                      let v = noise(vec2(data.t)) + plix_noise(vec2(data.t)) + smin(1., 2.);\n    \
+                      textureStore(texture, vec2(0u), vec4(palette(v), 1.));\n}";
+        let full = with_prelude(source, ShaderKind::Compute, &Output::default());
+        let diagnostics = crate::reflect::diagnostics(&full);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn directive_skips_prelude() {
+        let source = "// plix: no-prelude\nfn f() {}";
+        assert_eq!(
+            with_prelude(source, ShaderKind::Compute, &Output::default()),
+            source
+        );
+    }
+
+    #[test]
+    fn detects_fragment_shaders() {
+        let source = "fn mainImage(fragCoord: vec2<f32>) -> vec4<f32> { return vec4<f32>(1.); }";
+        assert_eq!(ShaderKind::detect(source), ShaderKind::Fragment);
+        naga::front::wgsl::parse_str(&with_prelude(
+            source,
+            ShaderKind::Fragment,
+            &Output::default(),
+        ))
+        .unwrap();
+
+        let source = "@compute @workgroup_size(1) fn main() {}";
+        assert_eq!(ShaderKind::detect(source), ShaderKind::Compute);
+
+        let commented = [
+            "// fn mainImage(fragCoord: vec2<f32>) -> vec4<f32>\n",
+            "/* fn mainImage /* nested */ fn mainImage */\n",
+            "fn mainImageHelper() {}\n",
+            "fn my_mainImage() {}\n",
+        ];
+        for prefix in commented {
+            let source = format!("{}@compute @workgroup_size(1) fn main() {{}}", prefix);
+            assert_eq!(
+                ShaderKind::detect(&source),
+                ShaderKind::Compute,
+                "{}",
+                prefix
+            );
+        }
+        let source =
+            "/* compute */ fn\n  mainImage(fragCoord: vec2<f32>) -> vec4<f32> { return vec4(1.); }";
+        assert_eq!(ShaderKind::detect(source), ShaderKind::Fragment);
+    }
+
+    #[test]
+    fn errors_point_to_user_lines() {
+        let source = "fn f() -> f32 {\n    return 1.;\n}\n\nfn g() { let x = ; }\n";
+        let full = with_prelude(source, ShaderKind::Compute, &Output::default());
+        let err = naga::front::wgsl::parse_str(&full).unwrap_err();
+        assert_eq!(err.location(&full).unwrap().line_number, 5);
+    }
+}
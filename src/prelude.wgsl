@@ -0,0 +1,91 @@
+// Plix prelude, appended to every shader unless it contains `// plix: no-prelude`.
+// Compute shaders also get the output texture, fragment shaders get the entry points.
+// Helpers start with `plix_` so they don't clash with the shader's own functions
+
+struct PlixData {
+    width: u32,
+    height: u32,
+    t: f32,
+    frame: u32,
+    // Like Shadertoy's iMouse: xy follows the pointer while the button is down, zw is
+    // where it went down, z is negative once it is released
+    mouse: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> data: PlixData;
+
+// Input textures from the `textures` list of the shader's toml, black when unset
+@group(2) @binding(0) var channel0: texture_2d<f32>;
+@group(2) @binding(1) var channel1: texture_2d<f32>;
+@group(2) @binding(2) var channel2: texture_2d<f32>;
+@group(2) @binding(3) var channel3: texture_2d<f32>;
+@group(2) @binding(4) var channel_sampler: sampler;
+
+fn plix_palette(t: f32) -> vec3<f32> {
+    let a = vec3<f32>(0.5, 0.5, 0.5);
+    let b = vec3<f32>(0.5, 0.34, 0.5);
+    let c = vec3<f32>(1.1, 1.2, 1.0);
+    let d = vec3<f32>(0.24, 0.4, 0.42);
+    return a + b * cos(6.28318 * (c * t + d));
+}
+
+fn plix_hash21(p: vec2<f32>) -> f32 {
+    var p3 = fract(vec3<f32>(p.xyx) * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn plix_hash22(p: vec2<f32>) -> vec2<f32> {
+    var p3 = fract(vec3<f32>(p.xyx) * vec3<f32>(0.1031, 0.1030, 0.0973));
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+fn plix_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+    return mix(
+        mix(plix_hash21(i), plix_hash21(i + vec2<f32>(1., 0.)), u.x),
+        mix(plix_hash21(i + vec2<f32>(0., 1.)), plix_hash21(i + vec2<f32>(1., 1.)), u.x),
+        u.y
+    );
+}
+
+fn plix_fbm(p: vec2<f32>) -> f32 {
+    var v = 0.;
+    var a = 0.5;
+    var q = p;
+    for (var i = 0; i < 5; i++) {
+        v += a * plix_noise(q);
+        q *= 2.;
+        a *= 0.5;
+    }
+    return v;
+}
+
+fn plix_smin(a: f32, b: f32, k: f32) -> f32 {
+    let h = max(k - abs(a - b), 0.) / k;
+    return min(a, b) - h * h * h * k * (1. / 6.);
+}
+
+fn plix_sdf_sphere(p: vec3<f32>, r: f32) -> f32 {
+    return length(p) - r;
+}
+
+fn plix_sdf_box(p: vec3<f32>, size: vec3<f32>) -> f32 {
+    let q = abs(p) - size;
+    return length(max(q, vec3<f32>(0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+fn plix_sdf_union(p1: f32, p2: f32) -> f32 {
+    return min(p1, p2);
+}
+
+fn plix_sdf_subtract(p1: f32, p2: f32) -> f32 {
+    return max(-p1, p2);
+}
+
+fn plix_sdf_intersect(p1: f32, p2: f32) -> f32 {
+    return max(p1, p2);
+}
@@ -0,0 +1 @@
+@group(1) @binding(0) var texture: texture_storage_2d<rgba8unorm, write>;
@@ -0,0 +1,30 @@
+// Sum of the samples of every pixel, row by row
+@group(1) @binding(2) var<storage, read_write> accumulation: array<vec4<f32>>;
+
+struct PlixSample {
+    // Samples taken before this one, 0 after time (unless `time_independent`), params or
+    // the size changed
+    index: u32,
+    // Different for every sample, for random numbers
+    seed: u32,
+};
+
+@group(1) @binding(3) var<uniform> sample: PlixSample;
+
+// Adds `color` to the samples of the pixel and stores their average in the output.
+// Call it once per pixel and step
+fn accumulate(coord: vec2<u32>, color: vec4<f32>) {
+    // Would land in the next row of `accumulation`
+    if coord.x >= data.width || coord.y >= data.height {
+        return;
+    }
+    let i = coord.y * data.width + coord.x;
+    // Outputs too big for one storage buffer only average the rows that fit
+    if i >= arrayLength(&accumulation) {
+        textureStore(texture, coord, color);
+        return;
+    }
+    let sum = select(vec4<f32>(0.), accumulation[i], sample.index > 0u) + color;
+    accumulation[i] = sum;
+    textureStore(texture, coord, sum / f32(sample.index + 1u));
+}
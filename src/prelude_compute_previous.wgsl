@@ -0,0 +1,8 @@
+@group(1) @binding(0) var texture: texture_storage_2d<rgba8unorm, write>;
+// Copy of the output from the last step
+@group(1) @binding(1) var previous_frame: texture_2d<f32>;
+
+// Pixel of the output from the last step
+fn previous(coord: vec2<u32>) -> vec4<f32> {
+    return textureLoad(previous_frame, coord, 0);
+}
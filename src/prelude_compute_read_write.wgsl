@@ -0,0 +1,6 @@
+@group(1) @binding(0) var texture: texture_storage_2d<rgba8unorm, read_write>;
+
+// Pixel of the output from the last step
+fn previous(coord: vec2<u32>) -> vec4<f32> {
+    return textureLoad(texture, coord);
+}
@@ -0,0 +1,16 @@
+struct PlixVertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn plix_vs(@location(0) position: vec3<f32>) -> PlixVertexOutput {
+    var out: PlixVertexOutput;
+    out.position = vec4<f32>(position, 1.0);
+    return out;
+}
+
+// fragCoord is the pixel center, with y = 0 on the same row as global_id.y = 0 in compute shaders
+@fragment
+fn plix_fs(in: PlixVertexOutput) -> @location(0) vec4<f32> {
+    return mainImage(in.position.xy);
+}
@@ -0,0 +1,344 @@
+use eframe::{
+    egui::{self, emath, epaint},
+    wgpu,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const HISTORY: usize = 240;
+/// Passes of a step that get their own timestamps, later ones are not measured
+pub const MAX_TIMED_PASSES: usize = 16;
+
+#[derive(Clone, Debug)]
+pub enum GpuTime {
+    /// Measured with timestamp queries around every compute or render pass
+    Timestamp {
+        /// From the start of the first pass to the end of the last
+        total: Duration,
+        passes: Vec<Duration>,
+    },
+    /// Time from submit until the queue reported the work as done
+    Submit(Duration),
+}
+
+impl GpuTime {
+    pub fn duration(&self) -> Duration {
+        match self {
+            GpuTime::Timestamp { total, .. } => *total,
+            GpuTime::Submit(d) => *d,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PerfSample {
+    pub gpu: Option<GpuTime>,
+    /// Workgroups of every compute pass
+    pub dispatch: Vec<[u32; 3]>,
+    /// Entry point of every compute pass, names the per-pass times
+    pub entry_points: Vec<String>,
+    /// Samples averaged in the output of a shader that accumulates
+    pub samples: Option<u32>,
+}
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    period: f32,
+}
+
+/// Measures how long the passes of a step take on the gpu. Uses timestamp queries
+/// when the device supports them, otherwise falls back to submit-to-complete timing
+pub struct GpuTimer {
+    timestamps: Option<Timestamps>,
+    /// Passes with timestamps in the read buffer
+    passes: usize,
+    pending: bool,
+    ready: Arc<AtomicBool>,
+    latest: Arc<Mutex<Option<GpuTime>>>,
+}
+
+impl GpuTimer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = (2 * MAX_TIMED_PASSES * std::mem::size_of::<u64>()) as u64;
+                Timestamps {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: None,
+                        ty: wgpu::QueryType::Timestamp,
+                        count: 2 * MAX_TIMED_PASSES as u32,
+                    }),
+                    resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: None,
+                        size,
+                        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    }),
+                    read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: None,
+                        size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    period: queue.get_timestamp_period(),
+                }
+            });
+
+        Self {
+            timestamps,
+            passes: 0,
+            pending: false,
+            ready: Arc::new(AtomicBool::new(false)),
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Timestamps for the `pass`-th pass of a step, `None` past `MAX_TIMED_PASSES`
+    pub fn pass_writes(&self, pass: usize) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.timestamps
+            .as_ref()
+            .filter(|_| pass < MAX_TIMED_PASSES)
+            .map(|t| wgpu::ComputePassTimestampWrites {
+                query_set: &t.query_set,
+                beginning_of_pass_write_index: Some(2 * pass as u32),
+                end_of_pass_write_index: Some(2 * pass as u32 + 1),
+            })
+    }
+
+    pub fn render_pass_writes(&self) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.timestamps
+            .as_ref()
+            .map(|t| wgpu::RenderPassTimestampWrites {
+                query_set: &t.query_set,
+                beginning_of_pass_write_index: Some(0),
+                end_of_pass_write_index: Some(1),
+            })
+    }
+
+    /// Copies the timestamps of the first `passes` passes to the read buffer, unless the
+    /// previous ones are still being read
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder, passes: usize) {
+        let Some(t) = &self.timestamps else {
+            return;
+        };
+        if self.pending || passes == 0 {
+            return;
+        }
+
+        self.passes = passes.min(MAX_TIMED_PASSES);
+        let queries = 2 * self.passes as u32;
+        encoder.resolve_query_set(&t.query_set, 0..queries, &t.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &t.resolve_buffer,
+            0,
+            &t.read_buffer,
+            0,
+            queries as u64 * std::mem::size_of::<u64>() as u64,
+        );
+    }
+
+    pub fn after_submit(&mut self, queue: &wgpu::Queue) {
+        match &self.timestamps {
+            Some(t) => {
+                if self.pending || self.passes == 0 {
+                    return;
+                }
+                self.pending = true;
+                let ready = self.ready.clone();
+                t.read_buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |r| {
+                        if r.is_ok() {
+                            ready.store(true, Ordering::Release);
+                        }
+                    });
+            }
+            None => {
+                let submitted = Instant::now();
+                let latest = self.latest.clone();
+                queue.on_submitted_work_done(move || {
+                    *latest.lock().unwrap() = Some(GpuTime::Submit(submitted.elapsed()));
+                });
+            }
+        }
+    }
+
+    /// Returns the most recent measurement, if a new one finished since the last call
+    pub fn poll(&mut self) -> Option<GpuTime> {
+        if let Some(t) = &self.timestamps {
+            if self.ready.swap(false, Ordering::AcqRel) {
+                let ts: Vec<u64> = {
+                    let view = t.read_buffer.slice(..).get_mapped_range();
+                    bytemuck::cast_slice(&view)[..2 * self.passes].to_vec()
+                };
+                t.read_buffer.unmap();
+                self.pending = false;
+
+                let duration =
+                    |ticks: u64| Duration::from_nanos((ticks as f64 * t.period as f64) as u64);
+                let passes = ts
+                    .chunks_exact(2)
+                    .map(|pass| duration(pass[1].saturating_sub(pass[0])))
+                    .collect();
+                return Some(GpuTime::Timestamp {
+                    total: duration(ts[ts.len() - 1].saturating_sub(ts[0])),
+                    passes,
+                });
+            }
+            return None;
+        }
+
+        self.latest.lock().unwrap().take()
+    }
+}
+
+/// Overlay showing cpu frame time, gpu compute time and the dispatch size
+pub struct Profiler {
+    pub visible: bool,
+
+    cpu: VecDeque<f32>,
+    gpu: VecDeque<f32>,
+    /// History of every timed pass, restarted when the number of passes changes
+    passes: Vec<VecDeque<f32>>,
+    last: Option<PerfSample>,
+
+    rx: mpsc::Receiver<PerfSample>,
+}
+
+impl Profiler {
+    pub fn new(rx: mpsc::Receiver<PerfSample>) -> Self {
+        Self {
+            visible: false,
+
+            cpu: VecDeque::with_capacity(HISTORY),
+            gpu: VecDeque::with_capacity(HISTORY),
+            passes: Vec::new(),
+            last: None,
+
+            rx,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        push(&mut self.cpu, dt * 1000.);
+        for sample in self.rx.try_iter() {
+            if let Some(gpu) = &sample.gpu {
+                push(&mut self.gpu, gpu.duration().as_secs_f32() * 1000.);
+            }
+            if let Some(GpuTime::Timestamp { passes, .. }) = &sample.gpu {
+                if self.passes.len() != passes.len() {
+                    self.passes = vec![VecDeque::with_capacity(HISTORY); passes.len()];
+                }
+                for (history, pass) in self.passes.iter_mut().zip(passes) {
+                    push(history, pass.as_secs_f32() * 1000.);
+                }
+            }
+            self.last = Some(PerfSample {
+                gpu: sample
+                    .gpu
+                    .or_else(|| self.last.as_ref().and_then(|s| s.gpu.clone())),
+                ..sample
+            });
+        }
+    }
+
+    pub fn render_overlay(&self, ctx: &egui::Context) {
+        if !self.visible {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("profiler"))
+            .anchor(emath::Align2::RIGHT_TOP, emath::vec2(-10., 10.))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!("CPU frame: {:.2} ms", average(&self.cpu)));
+                    match self.last.as_ref().and_then(|s| s.gpu.as_ref()) {
+                        Some(GpuTime::Timestamp { .. }) => {
+                            ui.label(format!("GPU compute: {:.3} ms", average(&self.gpu)))
+                        }
+                        Some(GpuTime::Submit(_)) => {
+                            ui.label(format!("GPU submit to done: {:.3} ms", average(&self.gpu)))
+                        }
+                        None => ui.label("GPU: waiting..."),
+                    };
+                    if self.passes.len() > 1 {
+                        let entry_points = self.last.as_ref().map(|s| &s.entry_points[..]);
+                        for (i, history) in self.passes.iter().enumerate() {
+                            let name = entry_points
+                                .and_then(|e| e.get(i))
+                                .cloned()
+                                .unwrap_or_else(|| format!("pass {}", i));
+                            ui.label(format!("  {}: {:.3} ms", name, average(history)));
+                        }
+                    }
+                    if let Some(s) = self.last.as_ref().filter(|s| !s.dispatch.is_empty()) {
+                        let sizes: Vec<_> = s
+                            .dispatch
+                            .iter()
+                            .map(|[x, y, z]| format!("{} x {} x {}", x, y, z))
+                            .collect();
+                        ui.label(format!("Dispatch: {}", sizes.join(", ")));
+                    }
+                    if let Some(samples) = self.last.as_ref().and_then(|s| s.samples) {
+                        ui.label(format!("Samples: {}", samples));
+                    }
+
+                    let (rect, _) =
+                        ui.allocate_exact_size(emath::vec2(240., 80.), egui::Sense::hover());
+                    let painter = ui.painter_at(rect);
+                    painter.rect_filled(rect, 2., ui.visuals().extreme_bg_color);
+                    let max = self
+                        .cpu
+                        .iter()
+                        .chain(self.gpu.iter())
+                        .fold(1f32, |a, &b| a.max(b));
+                    graph(&painter, rect, &self.cpu, max, epaint::Color32::LIGHT_BLUE);
+                    graph(&painter, rect, &self.gpu, max, epaint::Color32::LIGHT_GREEN);
+                    ui.label(format!("Scale: {:.1} ms (blue cpu, green gpu)", max));
+                });
+            });
+    }
+}
+
+fn push(history: &mut VecDeque<f32>, value: f32) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+fn average(history: &VecDeque<f32>) -> f32 {
+    let recent = history.iter().rev().take(30);
+    let count = recent.len().max(1);
+    recent.sum::<f32>() / count as f32
+}
+
+fn graph(
+    painter: &egui::Painter,
+    rect: emath::Rect,
+    history: &VecDeque<f32>,
+    max: f32,
+    color: epaint::Color32,
+) {
+    let points = history
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            emath::pos2(
+                rect.left() + rect.width() * i as f32 / (HISTORY - 1) as f32,
+                rect.bottom() - rect.height() * v / max,
+            )
+        })
+        .collect();
+    painter.add(epaint::Shape::line(points, epaint::Stroke::new(1., color)));
+}
@@ -0,0 +1,398 @@
+use eframe::wgpu::naga;
+
+use crate::{
+    compute::DATA_FIELDS,
+    prelude::{Shader, ShaderKind},
+    shader_meta::{Buffer, ShaderMeta},
+};
+
+/// Parses a shader and checks that the uniform at `@group(0) @binding(0)` matches
+/// the layout Plix writes. Returns a list of human readable problems
+pub fn check_shader(source: &str) -> Vec<String> {
+    let module = match naga::front::wgsl::parse_str(source) {
+        Ok(m) => m,
+        Err(e) => return vec![e.emit_to_string(source)],
+    };
+
+    let data = module.global_variables.iter().find(|(_, var)| {
+        var.binding
+            .as_ref()
+            .is_some_and(|b| b.group == 0 && b.binding == 0)
+    });
+    let Some((_, data)) = data else {
+        return Vec::new();
+    };
+
+    if data.space != naga::AddressSpace::Uniform {
+        return vec![format!(
+            "@group(0) @binding(0) must be var<uniform>, found {:?}",
+            data.space
+        )];
+    }
+    let naga::TypeInner::Struct { members, .. } = &module.types[data.ty].inner else {
+        return vec!["@group(0) @binding(0) must be a struct".into()];
+    };
+
+    let mut issues = Vec::new();
+    for &(name, ty, offset, required) in DATA_FIELDS {
+        let Some(member) = members.iter().find(|m| m.name.as_deref() == Some(name)) else {
+            if required {
+                issues.push(format!("Missing field `{}: {}`", name, ty));
+            }
+            continue;
+        };
+
+        if member.offset != offset {
+            issues.push(format!(
+                "Field `{}` is at offset {}, expected {}",
+                name, member.offset, offset
+            ));
+        }
+        let found = type_name(&module, member.ty);
+        if found != ty {
+            issues.push(format!("Field `{}` is {}, expected {}", name, found, ty));
+        }
+    }
+    for member in members.iter() {
+        let name = member.name.as_deref().unwrap_or("_");
+        if !DATA_FIELDS.iter().any(|(n, ..)| *n == name) {
+            issues.push(format!("Unknown field `{}` is not set by Plix", name));
+        }
+    }
+    issues
+}
+
+/// Parse or validation error of a shader
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based line of the error, `None` when naga doesn't know where the error is
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Parses and validates a shader with naga, errors point to lines in `source`
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let module = match naga::front::wgsl::parse_str(source) {
+        Ok(m) => m,
+        Err(e) => {
+            return vec![Diagnostic {
+                line: e.location(source).map(|l| l.line_number as usize),
+                message: e.message().into(),
+            }]
+        }
+    };
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    );
+    match validator.validate(&module) {
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            let mut message = e.as_inner().to_string();
+            let mut source_err: &dyn std::error::Error = e.as_inner();
+            while let Some(next) = source_err.source() {
+                message = format!("{}: {}", message, next);
+                source_err = next;
+            }
+            // Spans go from the function down to the expression that failed
+            let line = e.spans().last().map(|(span, _)| span.location(source));
+            vec![Diagnostic {
+                line: line.map(|l| l.line_number as usize),
+                message,
+            }]
+        }
+    }
+}
+
+/// Checks that the passes from the shader's toml have compute entry points. Passes covering
+/// the texture need its workgroup size, otherwise the dispatch doesn't fit the texture
+pub fn check_entry_points(source: &str, meta: &ShaderMeta) -> Vec<String> {
+    let Ok(module) = naga::front::wgsl::parse_str(source) else {
+        return Vec::new();
+    };
+    let expected = meta.workgroup_size();
+    let mut issues = Vec::new();
+    for pass in meta.passes() {
+        let name = &pass.entry_point;
+        let Some(entry) = module
+            .entry_points
+            .iter()
+            .find(|e| e.stage == naga::ShaderStage::Compute && e.name == *name)
+        else {
+            issues.push(format!("Compute entry point `{}` not found", name));
+            continue;
+        };
+        if !pass.dispatch.is_empty() {
+            continue;
+        }
+
+        let [x, y, z] = entry.workgroup_size;
+        if [x, y] != expected || z != 1 {
+            issues.push(format!(
+                "`{}` has @workgroup_size({}, {}, {}), expected ({}, {}, 1) from workgroup_size in the toml",
+                name, x, y, z, expected[0], expected[1]
+            ));
+        }
+    }
+    issues
+}
+
+/// Checks that every storage buffer from the toml is big enough for the type it's
+/// declared with, runtime sized arrays need at least one element
+pub fn check_buffers(source: &str, buffers: &[Buffer]) -> Vec<String> {
+    let Ok(module) = naga::front::wgsl::parse_str(source) else {
+        return Vec::new();
+    };
+    let mut layouter = naga::proc::Layouter::default();
+    if layouter.update(module.to_ctx()).is_err() {
+        return Vec::new();
+    }
+    buffers
+        .iter()
+        .filter_map(|buffer| {
+            let (_, var) = module
+                .global_variables
+                .iter()
+                .find(|(_, v)| v.name.as_deref() == Some(buffer.name.as_str()))?;
+            let needed = match module.types[var.ty].inner {
+                naga::TypeInner::Array {
+                    size: naga::ArraySize::Dynamic,
+                    stride,
+                    ..
+                } => stride as u64,
+                _ => layouter[var.ty].size as u64,
+            };
+            (needed > buffer.byte_size()).then(|| {
+                format!(
+                    "Buffer `{}` has {} bytes, `{}` needs at least {}",
+                    buffer.name,
+                    buffer.byte_size(),
+                    buffer.ty,
+                    needed
+                )
+            })
+        })
+        .collect()
+}
+
+fn type_name(module: &naga::Module, ty: naga::Handle<naga::Type>) -> String {
+    match &module.types[ty].inner {
+        naga::TypeInner::Scalar(s) => scalar_name(s),
+        naga::TypeInner::Vector { size, scalar } => {
+            format!("vec{}<{}>", *size as u8, scalar_name(scalar))
+        }
+        inner => format!("{:?}", inner),
+    }
+}
+
+fn scalar_name(s: &naga::Scalar) -> String {
+    let kind = match s.kind {
+        naga::ScalarKind::Sint => "i",
+        naga::ScalarKind::Uint => "u",
+        naga::ScalarKind::Float => "f",
+        naga::ScalarKind::Bool => return "bool".into(),
+        _ => "abstract ",
+    };
+    format!("{}{}", kind, s.width * 8)
+}
+
+/// First problem naga finds that would make creating the shader's pipeline fail, so
+/// threads sharing a device can reject a shader without raising a device error
+pub fn check_pipeline(shader: &Shader) -> Result<(), String> {
+    if let Some(d) = diagnostics(&shader.source).into_iter().next() {
+        return Err(d.message);
+    }
+    if shader.kind == ShaderKind::Fragment {
+        return Ok(());
+    }
+    let module = naga::front::wgsl::parse_str(&shader.source).map_err(|e| e.to_string())?;
+    for name in shader.meta.entry_points() {
+        let found = module
+            .entry_points
+            .iter()
+            .any(|e| e.stage == naga::ShaderStage::Compute && e.name == name);
+        if !found {
+            return Err(format!("Compute entry point `{}` not found", name));
+        }
+    }
+    match check_buffers(&shader.source, &shader.meta.buffers)
+        .into_iter()
+        .next()
+    {
+        Some(issue) => Err(issue),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader(data: &str) -> String {
+        format!(
+            "struct Data {{ {} }};\n@group(0) @binding(0) var<uniform> data: Data;\n\
+             @compute @workgroup_size(1) fn main() {{}}",
+            data
+        )
+    }
+
+    #[test]
+    fn matching_layout_has_no_issues() {
+        let issues = check_shader(&shader("width: u32, height: u32, t: f32, frame: u32,"));
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn missing_field() {
+        let issues = check_shader(&shader("width: u32, height: u32,"));
+        assert_eq!(issues, vec!["Missing field `t: f32`"]);
+    }
+
+    #[test]
+    fn mouse_is_optional() {
+        let issues = check_shader(&shader(
+            "width: u32, height: u32, t: f32, frame: u32, mouse: vec4<f32>,",
+        ));
+        assert!(issues.is_empty(), "{:?}", issues);
+        let issues = check_shader(&shader(
+            "width: u32, height: u32, t: f32, frame: u32, mouse: vec2<f32>,",
+        ));
+        assert_eq!(
+            issues,
+            vec!["Field `mouse` is vec2<f32>, expected vec4<f32>"]
+        );
+    }
+
+    #[test]
+    fn frame_is_optional() {
+        let issues = check_shader(&shader("width: u32, height: u32, t: f32,"));
+        assert!(issues.is_empty(), "{:?}", issues);
+        let issues = check_shader(&shader("width: u32, height: u32, t: f32, frame: f32,"));
+        assert_eq!(issues, vec!["Field `frame` is f32, expected u32"]);
+    }
+
+    #[test]
+    fn offsets_come_from_the_uniform() {
+        let issues = check_shader(&shader(
+            "width: u32, height: u32, t: f32, mouse: vec4<f32>,",
+        ));
+        assert!(issues.is_empty(), "{:?}", issues);
+        let issues = check_shader(&shader(
+            "width: u32, height: u32, t: f32, frame: u32, pad: vec4<f32>, mouse: vec4<f32>,",
+        ));
+        assert!(
+            issues.contains(&"Field `mouse` is at offset 32, expected 16".to_owned()),
+            "{:?}",
+            issues
+        );
+    }
+
+    #[test]
+    fn misordered_fields() {
+        let issues = check_shader(&shader("t: f32, width: u32, height: u32, frame: u32,"));
+        assert_eq!(issues.len(), 3, "{:?}", issues);
+        assert!(issues[0].contains("`width` is at offset 4, expected 0"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let issues = check_shader(&shader("width: u32, height: u32, t: u32, frame: u32,"));
+        assert_eq!(issues, vec!["Field `t` is u32, expected f32"]);
+    }
+
+    #[test]
+    fn no_uniform_is_fine() {
+        let issues = check_shader("@compute @workgroup_size(1) fn main() {}");
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn library_shaders_have_no_issues() {
+        for file in std::fs::read_dir("shaders").unwrap() {
+            let path = file.unwrap().path();
+            let is_shader = path
+                .extension()
+                .is_some_and(|e| crate::prelude::EXTENSIONS.iter().any(|ext| e == *ext));
+            if !is_shader && !crate::shadertoy::is_project(&path) {
+                continue;
+            }
+
+            let shader = crate::prelude::load_shader(path.to_str().unwrap()).unwrap();
+            let mut issues = check_shader(&shader.source);
+            issues.extend(diagnostics(&shader.source).into_iter().map(|d| d.message));
+            if shader.kind == crate::prelude::ShaderKind::Compute {
+                issues.extend(check_entry_points(&shader.source, &shader.meta));
+            }
+            issues.extend(check_buffers(&shader.source, &shader.meta.buffers));
+            assert!(issues.is_empty(), "{}: {:?}", path.display(), issues);
+        }
+    }
+
+    #[test]
+    fn workgroup_size_matches_toml() {
+        let source = "@compute @workgroup_size(8, 8) fn main() {}";
+        let meta = |toml: &str| toml::from_str::<ShaderMeta>(toml).unwrap();
+        assert!(check_entry_points(source, &meta("workgroup_size = [8, 8]")).is_empty());
+        assert_eq!(check_entry_points(source, &meta("")).len(), 1);
+    }
+
+    #[test]
+    fn missing_entry_point() {
+        let source = "@compute @workgroup_size(64) fn simulate() {}";
+        let meta: ShaderMeta = toml::from_str(
+            "[[pass]]\nentry_point = \"simulate\"\ndispatch = [\"4\"]\n\
+             [[pass]]\nentry_point = \"draw\"",
+        )
+        .unwrap();
+        let issues = check_entry_points(source, &meta);
+        assert_eq!(issues, vec!["Compute entry point `draw` not found"]);
+    }
+
+    #[test]
+    fn buffer_too_small_for_type() {
+        let buffer = |size| Buffer {
+            name: "cells".into(),
+            ty: "array<vec4<f32>, 4>".into(),
+            size,
+            init: Default::default(),
+            values: Vec::new(),
+        };
+        let source = "@group(3) @binding(0) var<storage, read_write> cells: array<vec4<f32>, 4>;";
+        assert!(check_buffers(source, &[buffer(64)]).is_empty());
+        assert_eq!(check_buffers(source, &[buffer(32)]).len(), 1);
+    }
+
+    #[test]
+    fn diagnostics_point_to_lines() {
+        let parse = diagnostics("fn f() {}\n\nfn main( {");
+        assert_eq!(parse.len(), 1);
+        assert_eq!(parse[0].line, Some(3));
+
+        let validation = diagnostics("fn f() -> f32 {\n    return 1u;\n}");
+        assert_eq!(validation.len(), 1);
+        assert_eq!(validation[0].line, Some(2), "{:?}", validation);
+
+        assert!(diagnostics("fn f() -> f32 { return 1.; }").is_empty());
+    }
+
+    #[test]
+    fn parse_error_is_reported() {
+        let issues = check_shader("fn main( {");
+        assert_eq!(issues.len(), 1);
+    }
+
+    #[test]
+    fn pipeline_problems_are_found_before_the_device() {
+        let source = "@compute @workgroup_size(1) fn main() {}";
+        let shader = |source: &str, meta: &str| Shader {
+            source: source.into(),
+            kind: ShaderKind::Compute,
+            meta: toml::from_str(meta).unwrap(),
+        };
+        assert!(check_pipeline(&shader(source, "")).is_ok());
+        assert!(check_pipeline(&shader("fn main( {", "")).is_err());
+        let other_pass = "[[pass]]\nentry_point = \"step\"";
+        assert!(check_pipeline(&shader(source, other_pass)).is_err());
+    }
+}
@@ -4,6 +4,14 @@ struct VertexOutput {
     @location(1) tex_coords: vec2<f32>,
 };
 
+struct View {
+    center: vec2<f32>,
+    zoom: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> view: View;
+
 @vertex
 fn vs_main(
     @location(0) position: vec3<f32>,
@@ -11,7 +19,7 @@ fn vs_main(
     var out: VertexOutput;
     out.color = vec3<f32>(0.2, 0.1, 0.4);
     out.clip_position = vec4<f32>(position, 1.0);
-    out.tex_coords = (position.xy + 1) / 2;
+    out.tex_coords = view.center + ((position.xy + 1) / 2 - 0.5) / view.zoom;
     return out;
 }
 
@@ -1,13 +1,33 @@
-use crate::texture::Texture;
+use crate::{
+    gpu,
+    inspector::PixelInfo,
+    texture::{self, Texture},
+};
 use bytemuck::{Pod, Zeroable};
 use eframe::{
     egui_wgpu,
     wgpu::{self, util::DeviceExt},
 };
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    mpsc, Arc,
+};
+
+/// Copies go both ways, so `Compute` can keep the last frame when the preview is resized
+const OUTPUT_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::TEXTURE_BINDING
+    .union(wgpu::TextureUsages::STORAGE_BINDING)
+    .union(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    .union(wgpu::TextureUsages::COPY_SRC)
+    .union(wgpu::TextureUsages::COPY_DST);
+
+/// States of `Renderer::pick_state`, set by the `map_async` callback
+const PICK_WAITING: u8 = 0;
+const PICK_READY: u8 = 1;
+const PICK_FAILED: u8 = 2;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
-struct Vertex {
+pub(crate) struct Vertex {
     position: [f32; 3],
 }
 impl Vertex {
@@ -19,7 +39,7 @@ impl Vertex {
         }
     }
 }
-const VERTICES: &[Vertex] = &[
+pub(crate) const VERTICES: &[Vertex] = &[
     Vertex {
         position: [1., 1., 0.0],
     },
@@ -34,8 +54,25 @@ const VERTICES: &[Vertex] = &[
     },
 ];
 
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, PartialEq)]
+pub struct ViewUniform {
+    center: [f32; 2],
+    zoom: f32,
+    _padding: f32,
+}
+impl ViewUniform {
+    pub fn new(center: [f32; 2], zoom: f32) -> Self {
+        Self {
+            center,
+            zoom,
+            _padding: 0.,
+        }
+    }
+}
+
 #[rustfmt::skip]
-const INDICES: &[u16] = &[
+pub(crate) const INDICES: &[u16] = &[
     0, 1, 2,
     0, 2, 3
 ];
@@ -49,22 +86,36 @@ pub struct Renderer {
     index_buffer: wgpu::Buffer,
 
     pub texture: Texture,
+    /// The device supports read-write rgba8 storage textures
+    read_write: bool,
     texture_bind_group: wgpu::BindGroup,
     texture_bind_group_layout: wgpu::BindGroupLayout,
+
+    view: ViewUniform,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    view_bind_group_layout: wgpu::BindGroupLayout,
+
+    pick_buffer: Arc<wgpu::Buffer>,
+    pick_pos: Option<[u32; 2]>,
+    /// Format of the texture the pending pick was copied from
+    pick_format: wgpu::TextureFormat,
+    pick_state: Arc<AtomicU8>,
+    pixel_tx: mpsc::Sender<PixelInfo>,
 }
 
 impl Renderer {
-    pub fn new(render_state: &egui_wgpu::RenderState, dim: [u32; 2]) -> Self {
+    pub fn new(
+        render_state: &egui_wgpu::RenderState,
+        dim: [u32; 2],
+        format: wgpu::TextureFormat,
+        pixel_tx: mpsc::Sender<PixelInfo>,
+    ) -> Self {
         let device = &render_state.device;
 
-        let texture_format = wgpu::TextureFormat::Rgba8Unorm;
-        let texture = Texture::new(
-            dim[0],
-            dim[1],
-            texture_format,
-            device,
-            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
-        );
+        let read_write = gpu::supports_read_write(&render_state.adapter, device);
+        let texture =
+            Texture::new(dim[0], dim[1], format, device, OUTPUT_USAGE).with_read_write(read_write);
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -99,6 +150,42 @@ impl Renderer {
             ],
         });
 
+        let view = ViewUniform::new([0.5, 0.5], 1.);
+        let view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[view]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let view_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &view_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: view_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pick_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(
@@ -123,7 +210,7 @@ impl Renderer {
         let pipeline = Self::create_pipeline(
             target_format.clone(),
             &shader,
-            &[&texture_bind_group_layout],
+            &[&texture_bind_group_layout, &view_bind_group_layout],
             device,
         );
 
@@ -136,20 +223,36 @@ impl Renderer {
             index_buffer,
 
             texture,
+            read_write,
             texture_bind_group,
             texture_bind_group_layout,
+
+            view,
+            view_buffer,
+            view_bind_group,
+            view_bind_group_layout,
+
+            pick_buffer: Arc::new(pick_buffer),
+            pick_pos: None,
+            pick_format: format,
+            pick_state: Arc::new(AtomicU8::new(PICK_WAITING)),
+            pixel_tx,
         }
     }
 
-    pub fn check_resize(&mut self, device: &wgpu::Device, dim: [u32; 2]) -> bool {
-        if self.texture.width != dim[0] || self.texture.height != dim[1] {
-            self.texture = Texture::new(
-                dim[0],
-                dim[1],
-                self.texture.format,
-                device,
-                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
-            );
+    /// Recreates the texture when its size or format changes
+    pub fn check_resize(
+        &mut self,
+        device: &wgpu::Device,
+        dim: [u32; 2],
+        format: wgpu::TextureFormat,
+    ) -> bool {
+        if self.texture.width != dim[0]
+            || self.texture.height != dim[1]
+            || self.texture.format != format
+        {
+            self.texture = Texture::new(dim[0], dim[1], format, device, OUTPUT_USAGE)
+                .with_read_write(self.read_write);
 
             self.texture_bind_group_layout =
                 device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -187,7 +290,10 @@ impl Renderer {
             self.pipeline = Self::create_pipeline(
                 self.target_format.clone(),
                 &self.shader,
-                &[&self.texture_bind_group_layout],
+                &[
+                    &self.texture_bind_group_layout,
+                    &self.view_bind_group_layout,
+                ],
                 device,
             );
             return true;
@@ -195,9 +301,92 @@ impl Renderer {
         false
     }
 
+    pub fn update_view(&mut self, queue: &wgpu::Queue, view: ViewUniform) {
+        if self.view != view {
+            self.view = view;
+            queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[self.view]));
+        }
+    }
+
+    /// Reads back a single pixel of the texture. The result is sent to the inspector
+    /// once the copy finishes, so it lags a frame or two behind the request
+    pub fn pick_pixel(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pos: Option<[u32; 2]>,
+    ) {
+        if let Some(pending) = self.pick_pos {
+            device.poll(wgpu::Maintain::Poll);
+            match self.pick_state.swap(PICK_WAITING, Ordering::AcqRel) {
+                PICK_READY => {
+                    let size = texture::pixel_size(self.pick_format) as u64;
+                    let view = self.pick_buffer.slice(..size).get_mapped_range();
+                    let _ = self.pixel_tx.send(PixelInfo {
+                        pos: pending,
+                        rgba: texture::to_rgba8(&view, self.pick_format),
+                        float: texture::to_rgba_f32(&view, self.pick_format),
+                    });
+                }
+                // Dropped, the next pick maps the buffer again
+                PICK_FAILED => {}
+                _ => return,
+            }
+            self.pick_buffer.unmap();
+            self.pick_pos = None;
+        }
+
+        let Some(pos) = pos else {
+            return;
+        };
+        if pos[0] >= self.texture.width || pos[1] >= self.texture.height {
+            return;
+        }
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.inner,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: pos[0],
+                    y: pos[1],
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.pick_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let state = self.pick_state.clone();
+        self.pick_format = self.texture.format;
+        self.pick_buffer
+            .slice(..texture::pixel_size(self.pick_format) as u64)
+            .map_async(wgpu::MapMode::Read, move |r| {
+                let done = if r.is_ok() { PICK_READY } else { PICK_FAILED };
+                state.store(done, Ordering::Release);
+            });
+        self.pick_pos = Some(pos);
+    }
+
     pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.view_bind_group, &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1)
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{export::ExportData, playback::Playback};
+
+/// Key of the settings in eframe's storage, window geometry is stored by eframe itself
+const KEY: &str = "plix_settings";
+
+/// State restored on the next launch
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Falls back to the first shader when it no longer exists
+    pub shader: Option<String>,
+    /// Param values of the selected shader by name
+    pub params: BTreeMap<String, f32>,
+    pub export: ExportData,
+    pub show_menu: bool,
+    pub show_editor: bool,
+    pub live_mode: bool,
+    pub playback: Playback,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            shader: None,
+            params: BTreeMap::new(),
+            export: ExportData::default(),
+            show_menu: true,
+            show_editor: false,
+            live_mode: false,
+            playback: Playback::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings of the previous run, or the defaults on the first launch
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|s| eframe::get_value(s, KEY))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, KEY, self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::Storage;
+
+    #[derive(Default)]
+    struct Memory(BTreeMap<String, String>);
+
+    impl eframe::Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.into(), value);
+        }
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn first_launch_uses_defaults() {
+        assert_eq!(Settings::load(None), Settings::default());
+        assert_eq!(
+            Settings::load(Some(&Memory::default())),
+            Settings::default()
+        );
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let mut storage = Memory::default();
+        storage.set_string(KEY, "(shader: Some(\"shaders/idk.wgsl\"))".into());
+        let settings = Settings::load(Some(&storage));
+        assert_eq!(settings.shader.as_deref(), Some("shaders/idk.wgsl"));
+        assert!(settings.show_menu);
+        assert_eq!(settings.export, ExportData::default());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let settings = Settings {
+            shader: Some("shaders/circles.wgsl".into()),
+            params: BTreeMap::from([("speed".into(), 2.)]),
+            show_menu: false,
+            playback: Playback {
+                paused: true,
+                time: 3.5,
+            },
+            ..Default::default()
+        };
+        let mut storage = Memory::default();
+        settings.save(&mut storage);
+        assert_eq!(Settings::load(Some(&storage)), settings);
+    }
+}
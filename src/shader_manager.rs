@@ -1,10 +1,124 @@
-use eframe::egui::{self, containers};
+use eframe::{
+    egui::{self, containers},
+    wgpu,
+};
+
+use crate::{
+    compute, glsl,
+    gpu::Gpu,
+    library,
+    prelude::{self, ShaderKind},
+    reflect,
+    shader_meta::{OutputFormat, ShaderMeta},
+    shadertoy,
+    thumbnails::Thumbnails,
+};
 use notify::Watcher;
-use std::sync::mpsc;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::mpsc,
+    time::SystemTime,
+};
+
+/// Size thumbnails are shown at in the library
+const TILE: f32 = 64.;
+/// Deleted shaders are moved here, outside of the watched folder
+const TRASH_DIR: &str = "trash";
+
+struct Entry {
+    path: String,
+    kind: ShaderKind,
+    meta: ShaderMeta,
+    modified: SystemTime,
+}
+
+impl Entry {
+    /// Name from the toml, or the file name without extension
+    fn name(&self) -> &str {
+        let file_name = self
+            .path
+            .split_once("/")
+            .map_or(self.path.as_str(), |s| s.1);
+        let file_name = file_name.split_once(".").map_or(file_name, |s| s.0);
+        self.meta.name.as_deref().unwrap_or(file_name)
+    }
+
+    fn label(&self) -> String {
+        match self.kind {
+            _ if glsl::is_glsl(&self.path) => format!("{} (glsl)", self.name()),
+            _ if shadertoy::is_project(Path::new(&self.path)) => {
+                format!("{} (shadertoy)", self.name())
+            }
+            ShaderKind::Compute => self.name().to_owned(),
+            ShaderKind::Fragment => format!("{} (fragment)", self.name()),
+        }
+    }
+
+    /// `search` is looked for in the name, path, description and tags, ignoring case
+    fn matches(&self, search: &str, tag: Option<&str>) -> bool {
+        if tag.is_some_and(|tag| !self.meta.tags.iter().any(|t| t == tag)) {
+            return false;
+        }
+        let search = search.trim().to_lowercase();
+        let description = self.meta.description.as_deref().unwrap_or_default();
+        search.is_empty()
+            || [self.name(), self.path.as_str(), description]
+                .into_iter()
+                .chain(self.meta.tags.iter().map(String::as_str))
+                .any(|text| text.to_lowercase().contains(&search))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sort {
+    Name,
+    /// Most recently changed first
+    Modified,
+}
+
+impl Sort {
+    fn label(&self) -> &'static str {
+        match self {
+            Sort::Name => "Name",
+            Sort::Modified => "Last modified",
+        }
+    }
+}
 
 pub struct ShaderManager {
-    shaders: Vec<String>,
+    shaders: Vec<Entry>,
     selected: String,
+    issues: Vec<String>,
+    /// Errors of the selected WGSL shader with the line they point to
+    diagnostics: Vec<reflect::Diagnostic>,
+
+    /// Toml of the selected shader and the current values of its params
+    meta: ShaderMeta,
+    params: Vec<f32>,
+    /// Output formats the preview adapter supports
+    output_formats: Vec<OutputFormat>,
+    kind: ShaderKind,
+    limits: wgpu::Limits,
+    /// Size of the preview, for the accumulation limit
+    canvas_size: [u32; 2],
+
+    import_path: String,
+    import_status: Option<Result<String, String>>,
+
+    new_name: String,
+    template: usize,
+    rename_to: String,
+    confirm_delete: bool,
+    file_status: Option<Result<String, String>>,
+
+    /// Set by the reset button until the app takes it
+    reset: bool,
+
+    thumbnails: Thumbnails,
+    search: String,
+    tag: Option<String>,
+    sort: Sort,
 
     app_tx: mpsc::Sender<String>,
     fs_rx: mpsc::Receiver<Result<notify::Event, notify::Error>>,
@@ -12,7 +126,8 @@ pub struct ShaderManager {
 }
 
 impl ShaderManager {
-    pub fn new(app_tx: mpsc::Sender<String>) -> Option<Self> {
+    /// Thumbnails are rendered on `gpu`
+    pub fn new(app_tx: mpsc::Sender<String>, gpu: Gpu) -> Option<Self> {
         let (tx, rx) = mpsc::channel();
         let mut watcher = notify::RecommendedWatcher::new(tx, notify::Config::default()).ok()?;
         watcher
@@ -25,6 +140,31 @@ impl ShaderManager {
         let mut s = Self {
             shaders: vec![],
             selected: String::new(),
+            issues: Vec::new(),
+            diagnostics: Vec::new(),
+
+            meta: ShaderMeta::default(),
+            params: Vec::new(),
+            output_formats: gpu.output_formats.clone(),
+            kind: ShaderKind::Compute,
+            limits: gpu.limits.clone(),
+            canvas_size: [0, 0],
+
+            import_path: String::new(),
+            import_status: None,
+
+            new_name: String::new(),
+            template: 0,
+            rename_to: String::new(),
+            confirm_delete: false,
+            file_status: None,
+
+            reset: false,
+
+            thumbnails: Thumbnails::new(gpu),
+            search: String::new(),
+            tag: None,
+            sort: Sort::Name,
 
             app_tx,
             fs_rx: rx,
@@ -62,7 +202,10 @@ impl ShaderManager {
             ) {
                 let path = e.paths.first().unwrap().to_str().unwrap();
                 if path.contains(&self.selected) {
-                    self.app_tx.send(self.selected.clone()).unwrap();
+                    self.notify();
+                } else if Path::new(path).ends_with(ShaderMeta::path(&self.selected)) {
+                    self.scan();
+                    self.notify();
                 } else {
                     self.scan();
                 }
@@ -76,15 +219,37 @@ impl ShaderManager {
         let files = std::fs::read_dir("shaders").expect("Failed to read shaders directory");
         for file in files {
             let file = file.unwrap().path();
-            if file.is_file() {
-                self.shaders.push(file.to_str().unwrap().into());
-            }
+            let is_shader = file
+                .extension()
+                .is_some_and(|e| prelude::EXTENSIONS.iter().any(|ext| e == *ext));
+            let kind = if shadertoy::is_project(&file) {
+                ShaderKind::Fragment
+            } else if file.is_file() && is_shader {
+                std::fs::read_to_string(&file)
+                    .map(|s| ShaderKind::detect(&s))
+                    .unwrap_or(ShaderKind::Compute)
+            } else {
+                continue;
+            };
+            let path: String = file.to_str().unwrap().into();
+            // Errors in the toml are reported when the shader is selected
+            let meta = ShaderMeta::load(&path).unwrap_or_default();
+            let modified = std::fs::metadata(&file)
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            self.thumbnails.request(&path);
+            self.shaders.push(Entry {
+                path,
+                kind,
+                meta,
+                modified,
+            });
         }
-        self.shaders.sort();
+        self.shaders.sort_by(|a, b| a.path.cmp(&b.path));
 
-        if !self.shaders.contains(&self.selected) {
-            self.selected = self.shaders[0].clone();
-            self.app_tx.send(self.selected.clone()).unwrap();
+        if !self.shaders.iter().any(|e| e.path == self.selected) {
+            self.selected = self.shaders[0].path.clone();
+            self.notify();
         }
     }
 
@@ -93,28 +258,422 @@ impl ShaderManager {
             .default_open(true)
             .show(ui, |ui| {
                 self.render_shaders(ui);
+                let [width, height] = self.canvas_size;
+                let size_issues =
+                    compute::dispatch_issues(self.kind, &self.meta, &self.limits, width, height);
+                for issue in self.issues.iter().chain(size_issues.iter()) {
+                    ui.label(
+                        egui::RichText::new(issue)
+                            .monospace()
+                            .color(ui.visuals().error_fg_color),
+                    );
+                }
                 ui.add_space(20.0);
                 if ui.button("Reload").clicked() {
                     self.scan();
                 }
             });
+
+        if !self.meta.params.is_empty() {
+            containers::CollapsingHeader::new("Parameters")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for (param, value) in self.meta.params.iter().zip(self.params.iter_mut()) {
+                        ui.add(egui::Slider::new(value, param.min..=param.max).text(&param.name));
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.params = self.meta.default_params();
+                    }
+                });
+        }
+
+        let output = &self.meta.output;
+        if !self.meta.buffers.is_empty() || output.read || output.accumulate {
+            containers::CollapsingHeader::new("Simulation")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for buffer in self.meta.buffers.iter() {
+                        ui.label(format!(
+                            "{}: {} ({} bytes)",
+                            buffer.name,
+                            buffer.ty,
+                            buffer.byte_size()
+                        ));
+                    }
+                    if self.meta.output.read {
+                        ui.label("output: accumulates with previous()");
+                    }
+                    if self.meta.output.accumulate {
+                        ui.label("output: averages samples with accumulate()");
+                        if !self.meta.output.time_independent {
+                            ui.label(
+                                "Samples restart whenever time changes, pause to let them add up",
+                            );
+                        }
+                        let [width, height] = self.canvas_size;
+                        if let Some(issue) =
+                            compute::accumulation_issue(&self.limits, width, height)
+                        {
+                            ui.colored_label(ui.visuals().error_fg_color, issue);
+                        }
+                    }
+                    if ui
+                        .button("Reset")
+                        .on_hover_text("Back to the initial buffers and a clear output (R)")
+                        .clicked()
+                    {
+                        self.reset = true;
+                    }
+                });
+        }
+
+        containers::CollapsingHeader::new("Manage shaders").show(ui, |ui| self.render_files(ui));
+
+        containers::CollapsingHeader::new("Import Shadertoy JSON").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File: ");
+                ui.text_edit_singleline(&mut self.import_path);
+            });
+            if ui.button("Import").clicked() {
+                self.import_status = Some(
+                    shadertoy::import(
+                        std::path::Path::new(self.import_path.trim()),
+                        std::path::Path::new("shaders"),
+                    )
+                    .map(|dir| dir.to_str().unwrap().to_owned()),
+                );
+                if let Some(Ok(dir)) = self.import_status.clone() {
+                    self.scan();
+                    self.selected = dir;
+                    self.notify();
+                }
+            }
+            match &self.import_status {
+                Some(Ok(dir)) => {
+                    ui.label(format!("Imported to {}", dir));
+                    ui.label("Only the Image pass is rendered, channels are placeholders");
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+                None => {}
+            }
+        });
+    }
+
+    /// Selects `shader` if it still exists and takes the param values by name, missing
+    /// ones use the default
+    pub fn restore(&mut self, shader: Option<&str>, params: &BTreeMap<String, f32>) {
+        if let Some(shader) = shader.filter(|s| self.shaders.iter().any(|e| e.path == *s)) {
+            if shader != self.selected {
+                self.selected = shader.into();
+                self.notify();
+            }
+        }
+        self.params = self.meta.param_values(params);
+    }
+
+    /// Size of the preview the selected shader renders to
+    pub fn set_canvas_size(&mut self, size: [u32; 2]) {
+        self.canvas_size = size;
+    }
+
+    /// Whether the simulation should start over, see `Compute::reset`
+    pub fn take_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset)
     }
 
     pub fn selected(&self) -> &str {
         &self.selected
     }
 
+    pub fn diagnostics(&self) -> &[reflect::Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn meta(&self) -> &ShaderMeta {
+        &self.meta
+    }
+
+    /// Param values in the order of the selected shader's toml
+    pub fn params(&self) -> &[f32] {
+        &self.params
+    }
+
+    pub fn named_params(&self) -> BTreeMap<String, f32> {
+        self.meta
+            .params
+            .iter()
+            .zip(self.params.iter())
+            .map(|(p, v)| (p.name.clone(), *v))
+            .collect()
+    }
+
     fn render_shaders(&mut self, ui: &mut egui::Ui) {
-        for shader in self.shaders.iter() {
-            let shader_name = shader.split_once("/").unwrap().1;
-            let shader_name = shader_name.split_once(".").unwrap().0;
-            if ui
-                .selectable_label(self.selected == *shader, shader_name)
-                .clicked()
-            {
-                self.selected = shader.into();
-                self.app_tx.send(self.selected.clone()).unwrap();
+        self.thumbnails.update(ui.ctx());
+
+        ui.horizontal(|ui| {
+            ui.label("Search: ");
+            ui.text_edit_singleline(&mut self.search);
+        });
+        ui.horizontal(|ui| {
+            let tags: BTreeSet<&str> = self
+                .shaders
+                .iter()
+                .flat_map(|e| e.meta.tags.iter().map(String::as_str))
+                .collect();
+            egui::ComboBox::from_id_source("shader_tag")
+                .selected_text(self.tag.as_deref().unwrap_or("All tags"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.tag, None, "All tags");
+                    for tag in tags {
+                        ui.selectable_value(&mut self.tag, Some(tag.to_owned()), tag);
+                    }
+                });
+            egui::ComboBox::from_id_source("shader_sort")
+                .selected_text(self.sort.label())
+                .show_ui(ui, |ui| {
+                    for sort in [Sort::Name, Sort::Modified] {
+                        ui.selectable_value(&mut self.sort, sort, sort.label());
+                    }
+                });
+        });
+        ui.add_space(10.0);
+
+        let mut visible: Vec<&Entry> = self
+            .shaders
+            .iter()
+            .filter(|e| e.matches(&self.search, self.tag.as_deref()))
+            .collect();
+        match self.sort {
+            Sort::Name => visible.sort_by_key(|e| e.name().to_lowercase()),
+            Sort::Modified => visible.sort_by_key(|e| std::cmp::Reverse(e.modified)),
+        }
+        if visible.is_empty() {
+            ui.label("No shaders match");
+        }
+
+        let mut clicked = None;
+        ui.horizontal_wrapped(|ui| {
+            for entry in visible {
+                let selected = self.selected == entry.path;
+                let inner = ui.vertical(|ui| {
+                    ui.set_width(TILE + 16.);
+                    let size = egui::vec2(TILE, TILE);
+                    let thumbnail = match self.thumbnails.get(&entry.path) {
+                        Some(Ok(texture)) => ui.add(
+                            egui::ImageButton::new(egui::load::SizedTexture::new(
+                                texture.id(),
+                                size,
+                            ))
+                            .selected(selected),
+                        ),
+                        Some(Err(e)) => ui
+                            .add(egui::Button::new("⚠").min_size(size).selected(selected))
+                            .on_hover_text(e),
+                        None => ui.add(egui::Button::new("…").min_size(size).selected(selected)),
+                    };
+                    let mut label = ui.selectable_label(selected, entry.label());
+                    if let Some(hover) = hover_text(&entry.meta) {
+                        label = label.on_hover_text(hover);
+                    }
+                    thumbnail.clicked() || label.clicked()
+                });
+                if inner.inner {
+                    clicked = Some(entry.path.clone());
+                }
             }
+        });
+        if let Some(description) = &self.meta.description {
+            ui.add_space(10.0);
+            ui.label(egui::RichText::new(description).weak());
+        }
+        if let Some(path) = clicked {
+            self.selected = path;
+            self.notify();
         }
     }
+
+    fn render_files(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Name: ");
+            ui.text_edit_singleline(&mut self.new_name);
+        });
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("shader_template")
+                .selected_text(library::TEMPLATES[self.template].name)
+                .show_ui(ui, |ui| {
+                    for (i, template) in library::TEMPLATES.iter().enumerate() {
+                        ui.selectable_value(&mut self.template, i, template.name);
+                    }
+                });
+            if ui.button("New from template").clicked() {
+                let template = &library::TEMPLATES[self.template];
+                let created = library::create(Path::new("shaders"), &self.new_name, template);
+                self.file_done(created.map(|path| (format!("Created {}", path.display()), path)));
+            }
+        });
+        ui.separator();
+
+        ui.label(format!("Selected: {}", self.selected));
+        ui.horizontal(|ui| {
+            ui.label("Rename to: ");
+            ui.text_edit_singleline(&mut self.rename_to);
+            if ui.button("Rename").clicked() {
+                let renamed = library::rename(Path::new(&self.selected), &self.rename_to);
+                self.file_done(
+                    renamed.map(|path| (format!("Renamed to {}", path.display()), path)),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Duplicate").clicked() {
+                let copy = library::duplicate(Path::new(&self.selected));
+                self.file_done(copy.map(|path| (format!("Copied to {}", path.display()), path)));
+            }
+            if !self.confirm_delete {
+                self.confirm_delete = ui.button("Delete").clicked();
+                return;
+            }
+            ui.label("Move to trash?");
+            if ui.button("Yes").clicked() {
+                self.delete_selected();
+            }
+            if ui.button("No").clicked() {
+                self.confirm_delete = false;
+            }
+        });
+
+        match &self.file_status {
+            Some(Ok(status)) => {
+                ui.label(status);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+            None => {}
+        }
+    }
+
+    /// Selects the new or renamed file, so the rescan keeps it instead of falling back
+    /// to the first shader
+    fn file_done(&mut self, result: Result<(String, std::path::PathBuf), String>) {
+        self.confirm_delete = false;
+        self.file_status = Some(result.map(|(status, path)| {
+            self.selected = path.to_str().unwrap().into();
+            self.scan();
+            self.notify();
+            status
+        }));
+    }
+
+    fn delete_selected(&mut self) {
+        self.confirm_delete = false;
+        if self.shaders.len() < 2 {
+            self.file_status = Some(Err("The last shader can't be deleted".into()));
+            return;
+        }
+        self.file_status = Some(
+            library::delete(Path::new(&self.selected), Path::new(TRASH_DIR)).map(|path| {
+                // The selection moves to the first shader left
+                self.scan();
+                format!("Moved to {}", path.display())
+            }),
+        );
+    }
+
+    /// Checks the selected shader and tells the app to reload it. Params keep their
+    /// values by name when the shader is saved
+    fn notify(&mut self) {
+        let previous = self.named_params();
+        self.diagnostics.clear();
+        (self.meta, self.issues) = match prelude::load_shader(&self.selected) {
+            Ok(shader) => {
+                self.kind = shader.kind;
+                // Translated GLSL doesn't have the lines of the file
+                if !glsl::is_glsl(&self.selected)
+                    && !shadertoy::is_project(Path::new(&self.selected))
+                {
+                    self.diagnostics = reflect::diagnostics(&shader.source);
+                }
+                let mut issues = reflect::check_shader(&shader.source);
+                if shader.kind == ShaderKind::Compute {
+                    issues.extend(reflect::check_entry_points(&shader.source, &shader.meta));
+                    issues.extend(reflect::check_buffers(&shader.source, &shader.meta.buffers));
+                }
+                let format = shader.meta.format();
+                if !self.output_formats.contains(&format) {
+                    issues.push(format!(
+                        "Output format {} is not supported by the preview adapter",
+                        format.wgsl()
+                    ));
+                }
+                if let Ok(manifest) = shadertoy::Manifest::load(Path::new(&self.selected)) {
+                    issues.extend(manifest.unrendered());
+                }
+                if glsl::is_glsl(&self.selected) && !shader.meta.params.is_empty() {
+                    issues.push("Params are only available in WGSL shaders".into());
+                }
+                (shader.meta, issues)
+            }
+            Err(e) => (
+                ShaderMeta::load(&self.selected).unwrap_or_default(),
+                vec![e.to_string()],
+            ),
+        };
+        self.params = self.meta.param_values(&previous);
+        self.thumbnails.request(&self.selected);
+        self.app_tx.send(self.selected.clone()).unwrap();
+    }
+}
+
+fn hover_text(meta: &ShaderMeta) -> Option<String> {
+    let mut lines = Vec::new();
+    if let Some(description) = &meta.description {
+        lines.push(description.clone());
+    }
+    if let Some(author) = &meta.author {
+        lines.push(format!("by {}", author));
+    }
+    if !meta.tags.is_empty() {
+        lines.push(format!("tags: {}", meta.tags.join(", ")));
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, meta: &str) -> Entry {
+        Entry {
+            path: path.into(),
+            kind: ShaderKind::Compute,
+            meta: toml::from_str(meta).unwrap(),
+            modified: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn search_looks_at_name_description_and_tags() {
+        let circles = entry(
+            "shaders/circles.wgsl",
+            "name = \"Circles\"\ndescription = \"Rings of color\"\ntags = [\"2d\"]",
+        );
+        assert_eq!(circles.name(), "Circles");
+        for search in ["", "circ", "RINGS", "2d", "shaders/"] {
+            assert!(circles.matches(search, None), "{}", search);
+        }
+        assert!(!circles.matches("tunnel", None));
+
+        assert!(circles.matches("", Some("2d")));
+        assert!(!circles.matches("circ", Some("3d")));
+    }
+
+    #[test]
+    fn name_falls_back_to_file_name() {
+        let tunnel = entry("shaders/tunnel.glsl", "");
+        assert_eq!(tunnel.name(), "tunnel");
+        assert_eq!(tunnel.label(), "tunnel (glsl)");
+    }
 }
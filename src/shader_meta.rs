@@ -0,0 +1,510 @@
+use eframe::wgpu;
+use serde::Deserialize;
+
+use crate::dispatch::Expr;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Optional `name.toml` next to a shader
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShaderMeta {
+    /// Shown instead of the file name
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub tags: Vec<String>,
+
+    /// Export defaults
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<f32>,
+    /// Samples per frame when exporting a shader that accumulates
+    pub samples: Option<u32>,
+    /// Time the library thumbnail is rendered at
+    pub thumbnail_time: Option<f32>,
+
+    /// Format of the output texture, `rgba8unorm` when unset
+    pub format: Option<OutputFormat>,
+    /// `@workgroup_size` of passes that cover the texture, their dispatch is divided by it
+    pub workgroup_size: Option<[u32; 2]>,
+    /// Compute passes run in order every step, `main` over the texture by default
+    #[serde(rename = "pass")]
+    pub passes: Vec<Pass>,
+    /// Declared as `u32` consts in WGSL and usable in dispatch sizes
+    pub constants: BTreeMap<String, u32>,
+    /// Images bound to `channel0-3` (`iChannel0-3` in GLSL), relative to the shader
+    pub textures: Vec<String>,
+    /// Fields of the `params` uniform, in order
+    #[serde(rename = "param")]
+    pub params: Vec<Param>,
+    /// Storage buffers at `@group(3)`, in order
+    #[serde(rename = "buffer")]
+    pub buffers: Vec<Buffer>,
+    pub output: Output,
+}
+
+/// How compute shaders use the output texture between frames
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    /// Declares `previous(coord)`, which returns the pixel of the last frame
+    pub read: bool,
+    /// Declares `accumulate(coord, color)`, which shows the average of every color passed
+    /// for the pixel since time, params or size last changed
+    pub accumulate: bool,
+    /// The shader ignores `data.t`, so samples keep adding up while time plays
+    pub time_independent: bool,
+    /// When the output goes back to black, otherwise it keeps the last frame
+    pub clear: Vec<ClearOn>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            read: false,
+            accumulate: false,
+            time_independent: false,
+            clear: vec![ClearOn::Resize, ClearOn::Reload, ClearOn::Reset],
+        }
+    }
+}
+
+/// Storage formats the output texture can have, named like in WGSL
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Rgba8unorm,
+    /// More precision for simulations and accumulation, values above 1 are kept
+    Rgba16float,
+    Rgba32float,
+}
+
+impl OutputFormat {
+    pub const ALL: [Self; 3] = [Self::Rgba8unorm, Self::Rgba16float, Self::Rgba32float];
+
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            Self::Rgba8unorm => wgpu::TextureFormat::Rgba8Unorm,
+            Self::Rgba16float => wgpu::TextureFormat::Rgba16Float,
+            Self::Rgba32float => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    pub fn from_texture_format(format: wgpu::TextureFormat) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.texture_format() == format)
+    }
+
+    pub fn wgsl(self) -> &'static str {
+        match self {
+            Self::Rgba8unorm => "rgba8unorm",
+            Self::Rgba16float => "rgba16float",
+            Self::Rgba32float => "rgba32float",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClearOn {
+    Resize,
+    /// A new version of the shader is swapped in
+    Reload,
+    /// The reset button
+    Reset,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Param {
+    pub name: String,
+    pub default: f32,
+    #[serde(default)]
+    pub min: f32,
+    #[serde(default = "one")]
+    pub max: f32,
+}
+
+fn one() -> f32 {
+    1.
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Pass {
+    pub entry_point: String,
+    /// Workgroups along x, y and z, missing ones are 1. Passes without it cover the texture
+    #[serde(default)]
+    pub dispatch: Vec<Expr>,
+}
+
+/// Storage buffer that keeps its contents between steps, for particles and simulations
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Buffer {
+    pub name: String,
+    /// WGSL type of the variable, usually a runtime sized array like `array<Particle>`
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// In bytes, rounded up to a multiple of 4
+    pub size: u64,
+    #[serde(default)]
+    pub init: Init,
+    /// Floats repeated over the whole buffer, used instead of `init`
+    #[serde(default)]
+    pub values: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Init {
+    #[default]
+    Zero,
+    /// Floats between 0 and 1, the same after every reset
+    Random,
+}
+
+impl Buffer {
+    pub fn byte_size(&self) -> u64 {
+        self.size.next_multiple_of(4).max(4)
+    }
+
+    /// Contents on load and after a reset
+    pub fn initial_contents(&self) -> Vec<u8> {
+        let len = (self.byte_size() / 4) as usize;
+        let floats: Vec<f32> = if !self.values.is_empty() {
+            self.values.iter().copied().cycle().take(len).collect()
+        } else {
+            match self.init {
+                Init::Zero => vec![0.; len],
+                Init::Random => (0..len as u32)
+                    .map(|i| pcg(i) as f32 / (u32::MAX as f32 + 1.))
+                    .collect(),
+            }
+        };
+        bytemuck::cast_slice(&floats).to_vec()
+    }
+}
+
+/// PCG hash, also seeds the samples of accumulating shaders
+pub fn pcg(i: u32) -> u32 {
+    let state = i.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+impl ShaderMeta {
+    pub fn path(shader: &str) -> PathBuf {
+        Path::new(shader).with_extension("toml")
+    }
+
+    /// Missing sidecars give the defaults
+    pub fn load(shader: &str) -> Result<Self, String> {
+        let path = Self::path(shader);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let meta: Self = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        meta.check()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(meta)
+    }
+
+    /// Problems the toml parser can't see, like unknown variables in dispatch sizes
+    pub fn check(&self) -> Result<(), String> {
+        for pass in self.passes.iter() {
+            if pass.dispatch.len() > 3 {
+                return Err(format!(
+                    "Pass `{}` has {} dispatch sizes, at most 3 are allowed",
+                    pass.entry_point,
+                    pass.dispatch.len()
+                ));
+            }
+            for expr in pass.dispatch.iter() {
+                let unknown = expr
+                    .variables()
+                    .into_iter()
+                    .find(|v| !["width", "height"].contains(v) && !self.constants.contains_key(*v));
+                if let Some(unknown) = unknown {
+                    return Err(format!(
+                        "Unknown `{}` in dispatch `{}` of pass `{}`, use width, height or a constant",
+                        unknown, expr, pass.entry_point
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn workgroup_size(&self) -> [u32; 2] {
+        self.workgroup_size.unwrap_or([1, 1])
+    }
+
+    pub fn passes(&self) -> Vec<Pass> {
+        if self.passes.is_empty() {
+            return vec![Pass {
+                entry_point: "main".into(),
+                dispatch: Vec::new(),
+            }];
+        }
+        self.passes.clone()
+    }
+
+    pub fn entry_points(&self) -> Vec<String> {
+        self.passes().into_iter().map(|p| p.entry_point).collect()
+    }
+
+    /// Workgroups `pass` dispatches for a `width` x `height` texture
+    pub fn dispatch_size(&self, pass: &Pass, width: u32, height: u32) -> Result<[u32; 3], String> {
+        if pass.dispatch.is_empty() {
+            let [x, y] = self.workgroup_size();
+            return Ok([width.div_ceil(x.max(1)), height.div_ceil(y.max(1)), 1]);
+        }
+        let variable = |name: &str| match name {
+            "width" => Some(width),
+            "height" => Some(height),
+            _ => self.constants.get(name).copied(),
+        };
+        let mut size = [1; 3];
+        for (size, expr) in size.iter_mut().zip(pass.dispatch.iter()) {
+            *size = expr.eval(&variable).map_err(|e| {
+                format!(
+                    "{} in dispatch `{}` of pass `{}`",
+                    e, expr, pass.entry_point
+                )
+            })?;
+        }
+        Ok(size)
+    }
+
+    pub fn texture_paths(&self, shader: &str) -> Vec<PathBuf> {
+        let dir = Path::new(shader).parent().unwrap_or(Path::new(""));
+        self.textures.iter().map(|t| dir.join(t)).collect()
+    }
+
+    pub fn default_params(&self) -> Vec<f32> {
+        self.params.iter().map(|p| p.default).collect()
+    }
+
+    /// Values in uniform order, taken from `values` by name or the default
+    pub fn param_values(&self, values: &BTreeMap<String, f32>) -> Vec<f32> {
+        self.params
+            .iter()
+            .map(|p| values.get(&p.name).copied().unwrap_or(p.default))
+            .collect()
+    }
+
+    /// Declaration of the `params` uniform, empty without params
+    pub fn params_wgsl(&self) -> String {
+        if self.params.is_empty() {
+            return String::new();
+        }
+        let fields: String = self
+            .params
+            .iter()
+            .map(|p| format!("    {}: f32,\n", p.name))
+            .collect();
+        format!(
+            "struct Params {{\n{}}};\n\n@group(0) @binding(1) var<uniform> params: Params;\n",
+            fields
+        )
+    }
+
+    /// Declarations of the constants, empty without constants
+    pub fn constants_wgsl(&self) -> String {
+        self.constants
+            .iter()
+            .map(|(name, value)| format!("const {}: u32 = {}u;\n", name, value))
+            .collect()
+    }
+
+    /// Declarations of the storage buffers, empty without buffers
+    pub fn buffers_wgsl(&self) -> String {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                format!(
+                    "@group(3) @binding({}) var<storage, read_write> {}: {};\n",
+                    i, b.name, b.ty
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sidecar() {
+        let meta: ShaderMeta = toml::from_str(
+            r#"
+            name = "Circles"
+            tags = ["2d"]
+            width = 1920
+            workgroup_size = [8, 8]
+
+            [[param]]
+            name = "speed"
+            default = 0.8
+            max = 4
+            "#,
+        )
+        .unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Circles"));
+        assert_eq!(meta.width, Some(1920));
+        assert_eq!(meta.height, None);
+        assert_eq!(meta.workgroup_size(), [8, 8]);
+        assert_eq!(
+            meta.params,
+            [Param {
+                name: "speed".into(),
+                default: 0.8,
+                min: 0.,
+                max: 4.
+            }]
+        );
+
+        let values = BTreeMap::from([("speed".to_owned(), 2.)]);
+        assert_eq!(meta.param_values(&values), [2.]);
+        assert_eq!(meta.param_values(&BTreeMap::new()), [0.8]);
+    }
+
+    #[test]
+    fn buffers_are_declared_and_filled() {
+        let meta: ShaderMeta = toml::from_str(
+            r#"
+            [[buffer]]
+            name = "particles"
+            type = "array<vec4<f32>>"
+            size = 30
+            init = "random"
+
+            [[buffer]]
+            name = "counts"
+            type = "array<f32>"
+            size = 12
+            values = [1, 2]
+            "#,
+        )
+        .unwrap();
+        let random = meta.buffers[0].initial_contents();
+        assert_eq!(random.len(), 32);
+        assert_eq!(random, meta.buffers[0].initial_contents());
+        let floats: &[f32] = bytemuck::cast_slice(&random);
+        assert!(floats.iter().all(|f| (0.0..1.).contains(f)));
+        assert!(floats.windows(2).any(|w| w[0] != w[1]));
+        let counts = meta.buffers[1].initial_contents();
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&counts), [1., 2., 1.]);
+
+        let wgsl = format!(
+            "{}\n@compute @workgroup_size(1) fn main() {{ counts[0] = particles[0].x; }}",
+            meta.buffers_wgsl()
+        );
+        naga::front::wgsl::parse_str(&wgsl).unwrap();
+    }
+
+    #[test]
+    fn passes_have_their_own_dispatch() {
+        let meta: ShaderMeta = toml::from_str(
+            r#"
+            workgroup_size = [8, 8]
+
+            [constants]
+            N = 1000
+
+            [[pass]]
+            entry_point = "simulate"
+            dispatch = ["N / 64"]
+
+            [[pass]]
+            entry_point = "draw"
+            "#,
+        )
+        .unwrap();
+        meta.check().unwrap();
+        assert_eq!(meta.entry_points(), ["simulate", "draw"]);
+        assert_eq!(ShaderMeta::default().entry_points(), ["main"]);
+
+        let passes = meta.passes();
+        assert_eq!(meta.dispatch_size(&passes[0], 100, 50), Ok([16, 1, 1]));
+        assert_eq!(meta.dispatch_size(&passes[1], 100, 50), Ok([13, 7, 1]));
+        let zero = Pass {
+            entry_point: "simulate".into(),
+            dispatch: vec!["N / (width - 100)".to_owned().try_into().unwrap()],
+        };
+        assert_eq!(
+            meta.dispatch_size(&zero, 100, 50),
+            Err("Division by zero in dispatch `N / (width - 100)` of pass `simulate`".into())
+        );
+
+        let wgsl = format!(
+            "{}\n@compute @workgroup_size(1) fn main() {{ let x = N; }}",
+            meta.constants_wgsl()
+        );
+        naga::front::wgsl::parse_str(&wgsl).unwrap();
+    }
+
+    #[test]
+    fn unknown_dispatch_variables_are_errors() {
+        let meta: ShaderMeta =
+            toml::from_str("[[pass]]\nentry_point = \"main\"\ndispatch = [\"M / 64\"]").unwrap();
+        assert!(meta.check().unwrap_err().contains("Unknown `M`"));
+        assert!(toml::from_str::<ShaderMeta>(
+            "[[pass]]\nentry_point = \"main\"\ndispatch = [\"N /\"]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn output_is_cleared_unless_told_otherwise() {
+        let meta = ShaderMeta::default();
+        assert!(!meta.output.read);
+        assert_eq!(meta.output.clear.len(), 3);
+
+        let meta: ShaderMeta =
+            toml::from_str("[output]\nread = true\nclear = [\"reset\"]").unwrap();
+        assert!(meta.output.read);
+        assert_eq!(meta.output.clear, [ClearOn::Reset]);
+    }
+
+    #[test]
+    fn output_format_defaults_to_rgba8() {
+        assert_eq!(ShaderMeta::default().format(), OutputFormat::Rgba8unorm);
+        let meta: ShaderMeta = toml::from_str("format = \"rgba16float\"").unwrap();
+        assert_eq!(
+            meta.format().texture_format(),
+            wgpu::TextureFormat::Rgba16Float
+        );
+        assert!(toml::from_str::<ShaderMeta>("format = \"bgra8unorm\"").is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_errors() {
+        assert!(toml::from_str::<ShaderMeta>("nmae = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn params_uniform_is_valid() {
+        let meta: ShaderMeta = toml::from_str(
+            "[[param]]\nname = \"a\"\ndefault = 1\n[[param]]\nname = \"b\"\ndefault = 0",
+        )
+        .unwrap();
+        let wgsl = format!(
+            "{}\n@compute @workgroup_size(1) fn main() {{ let x = params.a + params.b; }}",
+            meta.params_wgsl()
+        );
+        naga::front::wgsl::parse_str(&wgsl).unwrap();
+    }
+}
@@ -0,0 +1,440 @@
+//! Imports shaders exported from Shadertoy as JSON into a project folder in `shaders/`,
+//! with one GLSL file per render pass and a `project.toml` manifest describing how the
+//! passes are wired together.
+//!
+//! Only the Image pass (with Common in front of it) is rendered for now, channels read
+//! placeholder textures.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::glsl;
+
+pub const MANIFEST: &str = "project.toml";
+const TEXTURES: &str = "textures";
+const PLACEHOLDER_SIZE: u32 = 64;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    /// Response of the Shadertoy api
+    Wrapped {
+        #[serde(rename = "Shader")]
+        shader: Shader,
+    },
+    /// Export of the Shadertoy website, usually a single shader
+    List(Vec<Shader>),
+    Bare(Shader),
+}
+
+#[derive(Deserialize)]
+struct Shader {
+    info: Info,
+    renderpass: Vec<RenderPass>,
+}
+
+#[derive(Deserialize)]
+struct Info {
+    #[serde(default)]
+    id: String,
+    name: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct RenderPass {
+    #[serde(default)]
+    inputs: Vec<JsonInput>,
+    #[serde(default)]
+    outputs: Vec<JsonOutput>,
+    code: String,
+    name: String,
+    #[serde(rename = "type")]
+    kind: PassKind,
+}
+
+#[derive(Deserialize)]
+struct JsonInput {
+    id: serde_json::Value,
+    #[serde(alias = "filepath", default)]
+    src: String,
+    #[serde(alias = "type")]
+    ctype: String,
+    channel: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonOutput {
+    id: serde_json::Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PassKind {
+    Image,
+    Buffer,
+    Common,
+    Cubemap,
+    Sound,
+}
+
+/// `project.toml` of an imported project
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    /// Shadertoy id, `https://www.shadertoy.com/view/<id>`
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "pass")]
+    pub passes: Vec<Pass>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pass {
+    pub name: String,
+    pub kind: PassKind,
+    pub file: String,
+    #[serde(rename = "channel", default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Channel {
+    /// `iChannel<index>` in the pass
+    pub index: u32,
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Input {
+    /// Output of another pass of the project
+    Buffer { pass: String },
+    /// Image in the project folder. Starts as a placeholder, `source` is the original on Shadertoy
+    Texture { file: String, source: String },
+    /// Input Plix can't provide, like the keyboard, a webcam or music
+    Unsupported { ctype: String, source: String },
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(MANIFEST);
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn pass(&self, kind: PassKind) -> Option<&Pass> {
+        self.passes.iter().find(|p| p.kind == kind)
+    }
+
+    /// Passes `load` leaves out, and Image pass channels that read them
+    pub fn unrendered(&self) -> Vec<String> {
+        let mut issues: Vec<String> = self
+            .passes
+            .iter()
+            .filter(|p| !matches!(p.kind, PassKind::Image | PassKind::Common))
+            .map(|p| format!("{} pass is not rendered, only Image and Common are", p.name))
+            .collect();
+        let channels = self.pass(PassKind::Image).map(|p| p.channels.iter());
+        for channel in channels.into_iter().flatten() {
+            if let Input::Buffer { pass } = &channel.input {
+                issues.push(format!(
+                    "iChannel{} reads {}, which is not rendered",
+                    channel.index, pass
+                ));
+            }
+        }
+        issues
+    }
+}
+
+pub fn is_project(path: &Path) -> bool {
+    path.join(MANIFEST).is_file()
+}
+
+/// Translates the Image pass of a project to WGSL, see `glsl::translate`
+pub fn load(dir: &Path) -> Result<String, Vec<String>> {
+    let manifest = Manifest::load(dir).map_err(|e| vec![e])?;
+    let image = manifest
+        .pass(PassKind::Image)
+        .ok_or_else(|| vec![format!("{}: project has no image pass", dir.display())])?;
+
+    let mut files = Vec::new();
+    for pass in manifest.pass(PassKind::Common).into_iter().chain([image]) {
+        let path = dir.join(&pass.file);
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| vec![format!("{}: {}", path.display(), e)])?;
+        files.push((path.display().to_string(), source));
+    }
+
+    let files: Vec<_> = files
+        .iter()
+        .map(|(n, s)| (n.as_str(), s.as_str()))
+        .collect();
+    glsl::translate(&files)
+}
+
+/// Creates a project in `shaders_dir` from a Shadertoy JSON export and returns its folder
+pub fn import(json_path: &Path, shaders_dir: &Path) -> Result<PathBuf, String> {
+    let text = std::fs::read_to_string(json_path)
+        .map_err(|e| format!("{}: {}", json_path.display(), e))?;
+    let export: Export =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", json_path.display(), e))?;
+    let shader = match export {
+        Export::Wrapped { shader } | Export::Bare(shader) => shader,
+        Export::List(mut list) => {
+            if list.is_empty() {
+                return Err(format!("{}: no shaders in file", json_path.display()));
+            }
+            list.swap_remove(0)
+        }
+    };
+
+    let dir = unique_dir(shaders_dir, &slug(&shader.info.name));
+    std::fs::create_dir_all(dir.join(TEXTURES)).map_err(|e| e.to_string())?;
+
+    let manifest = manifest(&shader);
+    for (pass, json) in manifest.passes.iter().zip(shader.renderpass.iter()) {
+        std::fs::write(dir.join(&pass.file), &json.code).map_err(|e| e.to_string())?;
+        for channel in pass.channels.iter() {
+            if let Input::Texture { file, .. } = &channel.input {
+                let path = dir.join(file);
+                if !path.exists() {
+                    placeholder().save(&path).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+    }
+
+    let text = toml::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(MANIFEST), text).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn manifest(shader: &Shader) -> Manifest {
+    // Inputs reference other passes by the id of their output
+    let outputs: HashMap<String, &str> = shader
+        .renderpass
+        .iter()
+        .flat_map(|p| p.outputs.iter().map(|o| (id(&o.id), p.name.as_str())))
+        .collect();
+
+    let passes = shader
+        .renderpass
+        .iter()
+        .map(|pass| {
+            let mut channels: Vec<_> = pass
+                .inputs
+                .iter()
+                .map(|input| Channel {
+                    index: input.channel,
+                    input: match input.ctype.as_str() {
+                        "buffer" => Input::Buffer {
+                            pass: outputs
+                                .get(&id(&input.id))
+                                .map(|name| name.to_string())
+                                .unwrap_or_default(),
+                        },
+                        "texture" => Input::Texture {
+                            file: format!(
+                                "{}/{}.png",
+                                TEXTURES,
+                                Path::new(&input.src)
+                                    .file_stem()
+                                    .and_then(|s| s.to_str())
+                                    .unwrap_or("texture")
+                            ),
+                            source: input.src.clone(),
+                        },
+                        ctype => Input::Unsupported {
+                            ctype: ctype.into(),
+                            source: input.src.clone(),
+                        },
+                    },
+                })
+                .collect();
+            channels.sort_by_key(|c| c.index);
+
+            Pass {
+                name: pass.name.clone(),
+                kind: pass.kind,
+                file: format!("{}.glsl", slug(&pass.name)),
+                channels,
+            }
+        })
+        .collect();
+
+    Manifest {
+        name: shader.info.name.clone(),
+        author: shader.info.username.clone(),
+        description: shader.info.description.clone(),
+        id: shader.info.id.clone(),
+        passes,
+    }
+}
+
+/// Ids are strings in older exports and numbers in newer ones
+fn id(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+pub fn slug(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if slug.is_empty() {
+        "shadertoy".into()
+    } else {
+        slug
+    }
+}
+
+fn unique_dir(parent: &Path, name: &str) -> PathBuf {
+    let mut dir = parent.join(name);
+    let mut i = 2;
+    while dir.exists() {
+        dir = parent.join(format!("{}_{}", name, i));
+        i += 1;
+    }
+    dir
+}
+
+/// Magenta checkerboard, so missing textures stand out
+fn placeholder() -> image::RgbaImage {
+    image::RgbaImage::from_fn(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{"Shader": {
+        "ver": "0.1",
+        "info": {"id": "abc123", "name": "Test: Feedback!", "username": "someone", "description": "desc"},
+        "renderpass": [
+            {
+                "inputs": [
+                    {"id": "4dXGR8", "src": "/media/previz/buffer00.png", "ctype": "buffer", "channel": 0},
+                    {"id": 8, "src": "/media/a/0123abcd.jpg", "ctype": "texture", "channel": 1}
+                ],
+                "outputs": [{"id": "4dfGRr", "channel": 0}],
+                "code": "void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n    fragColor = vec4(f(), texture(iChannel0, fragCoord).rg, 1.0);\n}",
+                "name": "Image",
+                "description": "",
+                "type": "image"
+            },
+            {
+                "inputs": [{"id": 33, "src": "/presets/tex00.jpg", "ctype": "keyboard", "channel": 0}],
+                "outputs": [{"id": "4dXGR8", "channel": 0}],
+                "code": "void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n    fragColor = vec4(iTime);\n}",
+                "name": "Buffer A",
+                "description": "",
+                "type": "buffer"
+            },
+            {
+                "inputs": [],
+                "outputs": [],
+                "code": "float f() {\n    return 0.5;\n}",
+                "name": "Common",
+                "description": "",
+                "type": "common"
+            }
+        ]
+    }}"#;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = Path::new("target/shadertoy-tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn imports_passes_and_bindings() {
+        let dir = temp_dir("shadertoy_import");
+        let json = dir.join("export.json");
+        std::fs::write(&json, EXPORT).unwrap();
+
+        let project = import(&json, &dir).unwrap();
+        assert_eq!(project, dir.join("test_feedback"));
+        assert!(is_project(&project));
+
+        let manifest = Manifest::load(&project).unwrap();
+        assert_eq!(manifest.id, "abc123");
+        let files: Vec<_> = manifest.passes.iter().map(|p| p.file.as_str()).collect();
+        assert_eq!(files, ["image.glsl", "buffer_a.glsl", "common.glsl"]);
+        assert_eq!(
+            manifest.passes[0].channels,
+            [
+                Channel {
+                    index: 0,
+                    input: Input::Buffer {
+                        pass: "Buffer A".into()
+                    }
+                },
+                Channel {
+                    index: 1,
+                    input: Input::Texture {
+                        file: "textures/0123abcd.png".into(),
+                        source: "/media/a/0123abcd.jpg".into()
+                    }
+                },
+            ]
+        );
+        assert!(matches!(
+            &manifest.passes[1].channels[0].input,
+            Input::Unsupported { ctype, .. } if ctype == "keyboard"
+        ));
+        assert!(project.join("textures/0123abcd.png").is_file());
+
+        assert_eq!(
+            manifest.unrendered(),
+            [
+                "Buffer A pass is not rendered, only Image and Common are",
+                "iChannel0 reads Buffer A, which is not rendered",
+            ]
+        );
+
+        let wgsl = load(&project).unwrap();
+        naga::front::wgsl::parse_str(&wgsl).unwrap();
+
+        // Importing again doesn't overwrite the first project
+        assert_eq!(import(&json, &dir).unwrap(), dir.join("test_feedback_2"));
+    }
+
+    #[test]
+    fn website_export_is_a_list() {
+        let dir = temp_dir("shadertoy_list");
+        let json = dir.join("export.json");
+        let shader = EXPORT.trim_start_matches(r#"{"Shader": "#);
+        let shader = &shader[..shader.len() - 1];
+        std::fs::write(&json, format!("[{}]", shader)).unwrap();
+
+        let project = import(&json, &dir).unwrap();
+        assert_eq!(Manifest::load(&project).unwrap().passes.len(), 3);
+    }
+}
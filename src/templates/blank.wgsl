@@ -0,0 +1,12 @@
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= data.width || global_id.y >= data.height {
+        return;
+    }
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    let uv = vec2<f32>(global_id.xy) / size;
+
+    let color = 0.5 + 0.5 * cos(data.t + uv.xyx + vec3<f32>(0., 2., 4.));
+
+    textureStore(texture, global_id.xy, vec4<f32>(color, 1.));
+}
@@ -0,0 +1,21 @@
+// Trails from a feedback loop: every frame fades the last one and draws on top of it
+const DECAY: f32 = 0.94;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= data.width || global_id.y >= data.height {
+        return;
+    }
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    let uv = (vec2<f32>(global_id.xy) * 2. - size) / size.y;
+
+    let trail = previous(global_id.xy).rgb * DECAY;
+    let color = max(trail, shape(uv, data.t));
+    textureStore(texture, global_id.xy, vec4<f32>(color, 1.));
+}
+
+fn shape(uv: vec2<f32>, t: f32) -> vec3<f32> {
+    let center = 0.6 * vec2<f32>(sin(t * 1.3), sin(t * 2.1));
+    let d = length(uv - center);
+    return plix_palette(t * 0.2) * smoothstep(0.12, 0.08, d);
+}
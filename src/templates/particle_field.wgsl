@@ -0,0 +1,49 @@
+// Particles in a storage buffer that keep moving between steps. `fade` dims the last
+// frame, `simulate` moves every particle around a wandering attractor and splats it
+struct Particle {
+    pos: vec2<f32>,
+    vel: vec2<f32>,
+}
+
+const DECAY: f32 = 0.9;
+const PULL: f32 = 0.004;
+const DAMPING: f32 = 0.995;
+const SPEED: f32 = 0.01;
+
+@compute @workgroup_size(8, 8)
+fn fade(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= data.width || global_id.y >= data.height {
+        return;
+    }
+    let trail = previous(global_id.xy).rgb * DECAY;
+    textureStore(texture, global_id.xy, vec4<f32>(trail, 1.));
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let i = global_id.x;
+    if i >= PARTICLES {
+        return;
+    }
+    var particle = particles[i];
+    // The buffer starts out random between 0 and 1, velocities go both ways
+    if data.frame == 0u {
+        particle.vel -= 0.5;
+    }
+
+    let attractor = 0.5 + 0.25 * vec2<f32>(cos(data.t), sin(data.t * 1.3));
+    let to_attractor = attractor - particle.pos;
+    particle.vel += PULL * to_attractor / (dot(to_attractor, to_attractor) + 0.05);
+    particle.vel *= DAMPING;
+    particle.pos += SPEED * particle.vel;
+    particles[i] = particle;
+
+    // Square in the middle of the output, like uv in the other templates
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    let pixel = (particle.pos - 0.5) * size.y + size * 0.5;
+    if any(pixel < vec2<f32>(0.)) || any(pixel >= size) {
+        return;
+    }
+    let color = plix_palette(f32(i) / f32(PARTICLES) + 0.1 * data.t);
+    textureStore(texture, vec2<u32>(pixel), vec4<f32>(color, 1.));
+}
@@ -0,0 +1,54 @@
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= data.width || global_id.y >= data.height {
+        return;
+    }
+    let size = vec2<f32>(f32(data.width), f32(data.height));
+    let uv = (vec2<f32>(global_id.xy) * 2. - size) / size.y;
+
+    let ro = vec3<f32>(0., 0.5, -4.);
+    let rd = normalize(vec3<f32>(uv, 1.5));
+
+    var t = 0.;
+    var hit = false;
+    for (var i = 0; i < 100; i++) {
+        let d = scene(ro + rd * t);
+        if d < 0.001 {
+            hit = true;
+            break;
+        }
+        t += d;
+        if t > 50. {
+            break;
+        }
+    }
+
+    var color = vec3<f32>(0.05, 0.06, 0.1) + 0.1 * uv.y;
+    if hit {
+        let p = ro + rd * t;
+        let n = normal(p);
+        let light = normalize(vec3<f32>(0.6, 0.8, -0.5));
+        let diffuse = max(dot(n, light), 0.);
+        let ambient = 0.5 + 0.5 * n.y;
+        color = plix_palette(0.1 * t) * (0.8 * diffuse + 0.2 * ambient);
+    }
+
+    textureStore(texture, global_id.xy, vec4<f32>(color, 1.));
+}
+
+fn scene(p: vec3<f32>) -> f32 {
+    let sphere_pos = vec3<f32>(sin(data.t) * 1.2, 0.3 + 0.3 * sin(data.t * 2.), 0.);
+    let sphere = plix_sdf_sphere(p - sphere_pos, 0.6);
+    let box = plix_sdf_box(p, vec3<f32>(0.5));
+    let ground = p.y + 0.75;
+    return plix_smin(plix_smin(sphere, box, 0.5), ground, 0.2);
+}
+
+fn normal(p: vec3<f32>) -> vec3<f32> {
+    let e = vec2<f32>(0.001, 0.);
+    return normalize(vec3<f32>(
+        scene(p + e.xyy) - scene(p - e.xyy),
+        scene(p + e.yxy) - scene(p - e.yxy),
+        scene(p + e.yyx) - scene(p - e.yyx),
+    ));
+}
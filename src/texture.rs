@@ -1,4 +1,5 @@
 use eframe::wgpu;
+use std::sync::Arc;
 
 pub struct Texture {
     pub width: u32,
@@ -6,8 +7,11 @@ pub struct Texture {
 
     pub view: wgpu::TextureView,
     pub format: wgpu::TextureFormat,
-    pub inner: wgpu::Texture,
+    /// Shared with `Compute`, which copies from the output texture
+    pub inner: Arc<wgpu::Texture>,
     sampler: wgpu::Sampler,
+    /// The device can bind it as a read-write storage texture
+    pub read_write: bool,
 }
 
 impl Texture {
@@ -51,32 +55,349 @@ impl Texture {
             view,
             format,
             sampler,
-            inner: texture,
+            inner: Arc::new(texture),
+            read_write: false,
         }
     }
 
+    /// Marks the texture as usable as a read-write storage texture, see
+    /// `gpu::supports_read_write`, which only looks at rgba8
+    pub fn with_read_write(mut self, read_write: bool) -> Self {
+        self.read_write = read_write && self.format == wgpu::TextureFormat::Rgba8Unorm;
+        self
+    }
+
+    /// Float formats like rgba32float can't be filtered without an extra feature
+    fn filterable(&self) -> bool {
+        self.format.sample_type(None, None)
+            == Some(wgpu::TextureSampleType::Float { filterable: true })
+    }
+
     pub fn texture_binding_type(&self) -> wgpu::BindingType {
         wgpu::BindingType::Texture {
-            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            sample_type: wgpu::TextureSampleType::Float {
+                filterable: self.filterable(),
+            },
             view_dimension: wgpu::TextureViewDimension::D2,
             multisampled: false,
         }
     }
-    pub fn storage_texture_binding_type(&self) -> wgpu::BindingType {
+    pub fn storage_texture_binding_type(
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess,
+    ) -> wgpu::BindingType {
         wgpu::BindingType::StorageTexture {
-            access: wgpu::StorageTextureAccess::WriteOnly,
-            format: self.format,
+            access,
+            format,
             view_dimension: wgpu::TextureViewDimension::D2,
         }
     }
     pub fn sampler_binding_type(&self) -> wgpu::BindingType {
-        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        wgpu::BindingType::Sampler(match self.filterable() {
+            true => wgpu::SamplerBindingType::Filtering,
+            false => wgpu::SamplerBindingType::NonFiltering,
+        })
     }
 
-    pub fn texture_binding_resource(&self) -> wgpu::BindingResource {
+    pub fn texture_binding_resource(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::TextureView(&self.view)
     }
-    pub fn sampler_binding_resource(&self) -> wgpu::BindingResource {
+    pub fn sampler_binding_resource(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::Sampler(&self.sampler)
     }
 }
+
+impl Texture {
+    /// Uploads an image flipped vertically, so its bottom row ends up at row 0 like in
+    /// the output texture
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+    ) -> Self {
+        let texture = Self::new(
+            image.width(),
+            image.height(),
+            wgpu::TextureFormat::Rgba8Unorm,
+            device,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        queue.write_texture(
+            texture.inner.as_image_copy(),
+            &image::imageops::flip_vertical(image),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width() * 4),
+                rows_per_image: Some(image.height()),
+            },
+            texture.inner.size(),
+        );
+        texture
+    }
+
+    pub fn create_readback(&self, device: &wgpu::Device) -> Readback {
+        let bytes_per_row = padded_bytes_per_row(self.width, pixel_size(self.format));
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (bytes_per_row * self.height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Readback {
+            buffer,
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            bytes_per_row,
+        }
+    }
+}
+
+/// Staging buffer used to copy a texture back to the cpu, float formats are clamped to
+/// rgba8
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    bytes_per_row: u32,
+}
+
+impl Readback {
+    pub fn copy_args<'a>(
+        &'a self,
+        texture: &'a Texture,
+    ) -> (
+        wgpu::ImageCopyTexture<'a>,
+        wgpu::ImageCopyBuffer<'a>,
+        wgpu::Extent3d,
+    ) {
+        (
+            texture.inner.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        )
+    }
+
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, texture: &Texture) {
+        let (texture, buffer, size) = self.copy_args(texture);
+        encoder.copy_texture_to_buffer(texture, buffer, size);
+    }
+
+    /// Waits for the submission containing the copy to finish and returns the image
+    /// with row 0 at the bottom, matching what is shown in the preview
+    pub async fn read(
+        &self,
+        device: &wgpu::Device,
+        submission: wgpu::SubmissionIndex,
+    ) -> image::RgbaImage {
+        let buffer_slice = self.buffer.slice(..);
+        let (sender, receiver) = flume::bounded(1);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |r| sender.send(r).unwrap());
+        device
+            .poll(wgpu::Maintain::wait_for(submission))
+            .panic_on_timeout();
+        receiver.recv_async().await.unwrap().unwrap();
+
+        let imgbuf = {
+            let view = buffer_slice.get_mapped_range();
+            unpad_rows(
+                &view,
+                self.format,
+                self.width,
+                self.height,
+                self.bytes_per_row,
+            )
+        };
+        self.buffer.unmap();
+
+        imgbuf
+    }
+}
+
+/// Size of one row of `pixel_size` byte pixels in a copy buffer, rounded up to
+/// `COPY_BYTES_PER_ROW_ALIGNMENT`
+pub fn padded_bytes_per_row(width: u32, pixel_size: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * pixel_size).div_ceil(align) * align
+}
+
+/// Bytes of one pixel of an output format
+pub fn pixel_size(format: wgpu::TextureFormat) -> u32 {
+    format.block_copy_size(None).unwrap_or(4)
+}
+
+/// Converts one pixel of `format` to rgba8, clamping float formats to 0..1
+pub fn to_rgba8(pixel: &[u8], format: wgpu::TextureFormat) -> [u8; 4] {
+    match format {
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float => {
+            to_rgba_f32(pixel, format).map(|c| (c.clamp(0., 1.) * 255.).round() as u8)
+        }
+        _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+    }
+}
+
+/// Channels of one pixel of `format` as stored, float formats keep values outside 0..1
+pub fn to_rgba_f32(pixel: &[u8], format: wgpu::TextureFormat) -> [f32; 4] {
+    match format {
+        wgpu::TextureFormat::Rgba16Float => std::array::from_fn(|i| {
+            f16_to_f32(u16::from_le_bytes([pixel[2 * i], pixel[2 * i + 1]]))
+        }),
+        wgpu::TextureFormat::Rgba32Float => std::array::from_fn(|i| {
+            f32::from_le_bytes([
+                pixel[4 * i],
+                pixel[4 * i + 1],
+                pixel[4 * i + 2],
+                pixel[4 * i + 3],
+            ])
+        }),
+        _ => std::array::from_fn(|i| pixel[i] as f32 / 255.),
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1. } else { 1. };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0. => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1. + mantissa / 1024.) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Strips the row padding, converts the pixels to rgba8 and flips the image so row 0
+/// ends up at the bottom
+fn unpad_rows(
+    data: &[u8],
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+) -> image::RgbaImage {
+    let size = pixel_size(format);
+    let mut imgbuf = image::RgbaImage::new(width, height);
+    for (x, mut y, pixel) in imgbuf.enumerate_pixels_mut() {
+        y = height - y - 1;
+        let idx = (x * size + y * bytes_per_row) as usize;
+        *pixel = image::Rgba(to_rgba8(&data[idx..idx + size as usize], format));
+    }
+    imgbuf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTHS: [u32; 5] = [1, 63, 64, 65, 1921];
+
+    #[test]
+    fn bytes_per_row_is_aligned() {
+        for width in WIDTHS {
+            let bytes = padded_bytes_per_row(width, 4);
+            assert_eq!(
+                bytes % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                0,
+                "width {}",
+                width
+            );
+            assert!(bytes >= width * 4, "width {}", width);
+            assert!(
+                bytes - width * 4 < wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+                "width {}",
+                width
+            );
+        }
+    }
+
+    #[test]
+    fn aligned_width_is_not_padded() {
+        assert_eq!(padded_bytes_per_row(64, 4), 256);
+        assert_eq!(padded_bytes_per_row(128, 4), 512);
+    }
+
+    #[test]
+    fn odd_widths_are_padded() {
+        assert_eq!(padded_bytes_per_row(1, 4), 256);
+        assert_eq!(padded_bytes_per_row(63, 4), 256);
+        assert_eq!(padded_bytes_per_row(65, 4), 512);
+        assert_eq!(padded_bytes_per_row(1921, 4), 7936);
+    }
+
+    #[test]
+    fn unpad_keeps_every_pixel() {
+        let height = 3;
+        for width in WIDTHS {
+            let bytes_per_row = padded_bytes_per_row(width, 4);
+            let mut data = vec![0xAA; (bytes_per_row * height) as usize];
+            for y in 0..height {
+                for x in 0..width {
+                    let idx = (x * 4 + y * bytes_per_row) as usize;
+                    data[idx..idx + 4].copy_from_slice(&[x as u8, (x >> 8) as u8, y as u8, 255]);
+                }
+            }
+
+            let img = unpad_rows(
+                &data,
+                wgpu::TextureFormat::Rgba8Unorm,
+                width,
+                height,
+                bytes_per_row,
+            );
+            assert_eq!(img.dimensions(), (width, height));
+            for (x, y, pixel) in img.enumerate_pixels() {
+                let row = height - y - 1;
+                assert_eq!(pixel.0, [x as u8, (x >> 8) as u8, row as u8, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn float_pixels_are_clamped_to_rgba8() {
+        // 0, 0.5, 1 and 2 as f16
+        let half: Vec<u8> = [0x0000u16, 0x3800, 0x3c00, 0x4000]
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        assert_eq!(
+            to_rgba8(&half, wgpu::TextureFormat::Rgba16Float),
+            [0, 128, 255, 255]
+        );
+        let float: Vec<u8> = [-1f32, 0.25, 1., 8.]
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        assert_eq!(
+            to_rgba8(&float, wgpu::TextureFormat::Rgba32Float),
+            [0, 64, 255, 255]
+        );
+        assert_eq!(
+            to_rgba_f32(&half, wgpu::TextureFormat::Rgba16Float),
+            [0., 0.5, 1., 2.]
+        );
+        assert_eq!(
+            to_rgba_f32(&float, wgpu::TextureFormat::Rgba32Float),
+            [-1., 0.25, 1., 8.]
+        );
+        assert_eq!(
+            to_rgba_f32(&[0, 51, 255, 255], wgpu::TextureFormat::Rgba8Unorm),
+            [0., 0.2, 1., 1.]
+        );
+        let rows = padded_bytes_per_row(65, 8);
+        assert_eq!(rows % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
+        assert!(rows >= 65 * 8);
+    }
+}
@@ -0,0 +1,225 @@
+use eframe::{egui, wgpu};
+use pollster::FutureExt;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use crate::{
+    compute::Compute,
+    gpu::{self, Gpu},
+    prelude::{self, Shader},
+    reflect,
+    texture::Texture,
+};
+
+pub const SIZE: u32 = 96;
+/// Used when the shader's toml has no `thumbnail_time`
+const DEFAULT_TIME: f32 = 1.;
+/// Averaged for shaders that accumulate, enough to hide most noise at this size
+const SAMPLES: u32 = 16;
+const CACHE_DIR: &str = "cache/thumbnails";
+
+/// Renders small previews of the library on a background thread. Images are cached
+/// on disk by a hash of everything that affects them, so only changed shaders render
+pub struct Thumbnails {
+    request_tx: mpsc::Sender<String>,
+    done_rx: mpsc::Receiver<(String, Result<image::RgbaImage, String>)>,
+    _thread: std::thread::JoinHandle<()>,
+
+    textures: HashMap<String, Result<egui::TextureHandle, String>>,
+}
+
+impl Thumbnails {
+    pub fn new(gpu: Gpu) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let thread = std::thread::spawn(move || thumbnail_thread(gpu, request_rx, done_tx));
+
+        Self {
+            request_tx,
+            done_rx,
+            _thread: thread,
+
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Renders `path` again if it changed since its thumbnail was made
+    pub fn request(&self, path: &str) {
+        let _ = self.request_tx.send(path.into());
+    }
+
+    /// Uploads finished thumbnails to egui
+    pub fn update(&mut self, ctx: &egui::Context) {
+        while let Ok((path, image)) = self.done_rx.try_recv() {
+            let texture = image.map(|image| {
+                let size = [image.width() as usize, image.height() as usize];
+                let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+                ctx.load_texture(&path, image, egui::TextureOptions::LINEAR)
+            });
+            self.textures.insert(path, texture);
+        }
+    }
+
+    /// `None` while the first thumbnail of `path` is rendering, the error if it failed
+    pub fn get(&self, path: &str) -> Option<Result<&egui::TextureHandle, &str>> {
+        self.textures
+            .get(path)
+            .map(|t| t.as_ref().map_err(String::as_str))
+    }
+}
+
+fn thumbnail_thread(
+    gpu: Gpu,
+    request_rx: mpsc::Receiver<String>,
+    done_tx: mpsc::Sender<(String, Result<image::RgbaImage, String>)>,
+) {
+    while let Ok(path) = request_rx.recv() {
+        // A rescan requests everything again, each shader only has to be looked at once
+        let mut paths = BTreeSet::from([path]);
+        paths.extend(request_rx.try_iter());
+        for path in paths {
+            let image = thumbnail(&gpu, &path);
+            if done_tx.send((path, image)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn thumbnail(gpu: &Gpu, path: &str) -> Result<image::RgbaImage, String> {
+    let shader = prelude::load_shader(path).map_err(|e| e.to_string())?;
+    let time = shader.meta.thumbnail_time.unwrap_or(DEFAULT_TIME);
+
+    let cached = cache_path(&shader, path, time);
+    if let Ok(image) = image::open(&cached) {
+        return Ok(image.to_rgba8());
+    }
+    // Broken shaders would raise errors on the preview device
+    reflect::check_pipeline(&shader)?;
+
+    let image = render(gpu, path, shader, time)?;
+    let saved = std::fs::create_dir_all(CACHE_DIR)
+        .map_err(|e| e.to_string())
+        .and_then(|_| image.save(&cached).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        println!("Failed to cache thumbnail of {}: {}", path, e);
+    }
+    Ok(image)
+}
+
+fn render(gpu: &Gpu, path: &str, shader: Shader, time: f32) -> Result<image::RgbaImage, String> {
+    let texture = Texture::new(
+        SIZE,
+        SIZE,
+        gpu.output_format(shader.meta.format())?,
+        &gpu.device,
+        wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    )
+    .with_read_write(gpu.read_write);
+    let readback = texture.create_readback(&gpu.device);
+
+    // The preview shares the device, its compiles take the same error scope lock
+    let submission = gpu::with_error_scope(&gpu.device, || {
+        let mut compute = Compute::from_shader(&gpu.device, &gpu.queue, &texture, path, shader);
+        compute.update_time(&gpu.queue, time);
+        if compute.samples().is_some() {
+            for _ in 1..SAMPLES {
+                compute.step(&gpu.device, &gpu.queue, None);
+            }
+        }
+        compute.step(&gpu.device, &gpu.queue, Some(readback.copy_args(&texture)))
+    })?;
+    Ok(readback.read(&gpu.device, submission).block_on())
+}
+
+/// Thumbnails are named after a hash of the final source, the output format, the time and
+/// the textures
+fn cache_path(shader: &Shader, path: &str, time: f32) -> PathBuf {
+    let mut hash = Fnv::default();
+    hash.write(shader.source.as_bytes());
+    hash.write(shader.meta.format().wgsl().as_bytes());
+    hash.write(&time.to_le_bytes());
+    hash.write(&SIZE.to_le_bytes());
+    for texture in shader.meta.texture_paths(path) {
+        hash.write(&std::fs::read(texture).unwrap_or_default());
+    }
+    Path::new(CACHE_DIR).join(format!("{:016x}.png", hash.0))
+}
+
+/// FNV-1a, unlike `DefaultHasher` its output is the same across Rust versions
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv_matches_reference() {
+        let mut hash = Fnv::default();
+        hash.write(b"a");
+        assert_eq!(hash.0, 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn cache_key_follows_content() {
+        let path = "shaders/idk.wgsl";
+        let shader = prelude::load_shader(path).unwrap();
+        let key = cache_path(&shader, path, 1.);
+        assert_eq!(key, cache_path(&shader.clone(), path, 1.));
+        assert_ne!(key, cache_path(&shader, path, 2.));
+
+        let mut edited = shader.clone();
+        edited.source.push_str("\n// edit");
+        assert_ne!(key, cache_path(&edited, path, 1.));
+    }
+
+    #[test]
+    fn broken_shaders_have_no_thumbnail() {
+        let gpu = crate::golden_tests::gpu();
+        let path = "target/thumbnail-tests/broken.wgsl";
+        std::fs::create_dir_all("target/thumbnail-tests").unwrap();
+        std::fs::write(
+            path,
+            "@compute @workgroup_size(1) fn main() { let x: f32 = 1u; }",
+        )
+        .unwrap();
+        assert!(thumbnail(gpu, path).is_err());
+    }
+
+    #[test]
+    fn device_errors_fail_the_thumbnail() {
+        let gpu = crate::golden_tests::gpu();
+        let source = "@compute @workgroup_size(4096) fn main() {}";
+        let shader = prelude::compose_shader("large.wgsl", source, Default::default()).unwrap();
+        assert!(render(gpu, "large.wgsl", shader, 1.).is_err());
+    }
+
+    #[test]
+    fn renders_library_shader() {
+        let gpu = crate::golden_tests::gpu();
+        let shader = prelude::load_shader("shaders/circles.wgsl").unwrap();
+        let image = render(gpu, "shaders/circles.wgsl", shader, 1.).unwrap();
+        assert_eq!(image.dimensions(), (SIZE, SIZE));
+        assert!(image.pixels().any(|p| p.0 != image.get_pixel(0, 0).0));
+    }
+}